@@ -38,3 +38,4 @@ cargo rustdoc --all-features -- -D warnings
 cargo test --doc
 cargo test --all-targets
 cargo test --all-targets --features forwarded-header
+cargo test --all-targets --all-features
@@ -0,0 +1,146 @@
+//! [`actix-web`](https://docs.rs/actix-web) extractor
+//!
+//! [`ClientIp`] is configured by [`ClientIpConfig`] app data, the
+//! [peer address](HttpRequest::peer_addr) is checked against trusted proxies:
+//!
+//! ```
+//! use actix_web::{App, web};
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource};
+//!
+//! async fn handler(ClientIp(ip): ClientIp) -> String {
+//!     ip.to_string()
+//! }
+//!
+//! let app = App::new()
+//!     .app_data(
+//!         ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+//!             .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+//!     )
+//!     .route("/", web::get().to(handler));
+//! ```
+
+use std::future::{Ready, ready};
+
+use actix_web::{
+    FromRequest, HttpRequest, ResponseError,
+    dev::Payload,
+    http::{StatusCode, header::HeaderMap},
+};
+use http::HeaderName;
+
+use crate::{ClientIp, ClientIpConfig, ClientIpRejection, HeaderAccess};
+
+impl HeaderAccess for HeaderMap {
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+        self.get_all(header_name.as_str()).map(|v| v.as_bytes())
+    }
+}
+
+impl FromRequest for ClientIp {
+    type Error = ClientIpRejection;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.app_data::<ClientIpConfig>()
+                .ok_or(ClientIpRejection::MissingSource)
+                .and_then(|config| {
+                    let peer_ip = req.peer_addr().map(|addr| addr.ip());
+                    Ok(ClientIp(config.extract(req.headers(), peer_ip)?))
+                }),
+        )
+    }
+}
+
+impl ResponseError for ClientIpRejection {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+    use crate::{ClientIpSource, Error};
+
+    fn extract(req: TestRequest) -> Result<ClientIp, ClientIpRejection> {
+        ClientIp::from_request(&req.to_http_request(), &mut Payload::None).into_inner()
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        let trusted = config
+            .clone()
+            .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+
+        assert_eq!(
+            extract(TestRequest::default().insert_header(("x-real-ip", "1.2.3.4"))).unwrap_err(),
+            ClientIpRejection::MissingSource
+        );
+        assert_eq!(
+            extract(TestRequest::default().app_data(config.clone())).unwrap_err(),
+            ClientIpRejection::Extraction(Error::AbsentHeader {
+                header_name: HeaderName::from_static("x-real-ip")
+            })
+        );
+        assert_eq!(
+            extract(
+                TestRequest::default()
+                    .app_data(config)
+                    .insert_header(("x-real-ip", "1.2.3.4"))
+            )
+            .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap())
+        );
+
+        assert_eq!(
+            extract(
+                TestRequest::default()
+                    .app_data(trusted.clone())
+                    .insert_header(("x-real-ip", "1.2.3.4"))
+            )
+            .unwrap_err(),
+            ClientIpRejection::MissingPeerAddr
+        );
+        assert_eq!(
+            extract(
+                TestRequest::default()
+                    .app_data(trusted.clone())
+                    .peer_addr("10.0.0.1:1234".parse().unwrap())
+                    .insert_header(("x-real-ip", "1.2.3.4"))
+            )
+            .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+        assert_eq!(
+            extract(
+                TestRequest::default()
+                    .app_data(trusted)
+                    .peer_addr("5.6.7.8:1234".parse().unwrap())
+                    .insert_header(("x-real-ip", "1.2.3.4"))
+            )
+            .unwrap(),
+            ClientIp("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+    }
+
+    #[test]
+    fn test_rejection_status() {
+        assert_eq!(
+            ClientIpRejection::MissingSource.status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            ClientIpRejection::Extraction(Error::AbsentHeader {
+                header_name: HeaderName::from_static("x-real-ip")
+            })
+            .status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! [`axum`](https://docs.rs/axum) extractors
+//!
+//! [`ClientIp`] is configured via request extensions, either by
+//! [`ClientIpLayer`](crate::tower::ClientIpLayer) (requires `tower` feature) or
+//! by a [`ClientIpConfig`] extension:
+//!
+//! ```
+//! use std::net::SocketAddr;
+//!
+//! use axum::{Extension, Router, routing::get};
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource};
+//!
+//! async fn handler(ClientIp(ip): ClientIp) -> String {
+//!     ip.to_string()
+//! }
+//!
+//! let config = ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+//!     .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+//! let app = Router::new()
+//!     .route("/", get(handler))
+//!     .layer(Extension(config))
+//!     .into_make_service_with_connect_info::<SocketAddr>();
+//! ```
+//!
+//! With [trusted proxies](ClientIpConfig::trusted_proxies) configured, the
+//! peer IP is taken from [`ConnectInfo<SocketAddr>`](ConnectInfo).
+//! [`ClientIpFromState`] takes the configuration from the router state
+//! instead:
+//!
+//! ```
+//! use axum::{Router, routing::get};
+//! use client_ip::{ClientIpConfig, ClientIpSource, axum::ClientIpFromState};
+//!
+//! async fn handler(ClientIpFromState(ip): ClientIpFromState) -> String {
+//!     ip.to_string()
+//! }
+//!
+//! let app: Router = Router::new()
+//!     .route("/", get(handler))
+//!     .with_state(ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor));
+//! ```
+
+use std::net::{IpAddr, SocketAddr};
+
+use ::axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    response::{IntoResponse, Response},
+};
+use http::request::Parts;
+
+use crate::{ClientIp, ClientIpConfig, ClientIpRejection};
+
+impl IntoResponse for ClientIpRejection {
+    fn into_response(self) -> Response {
+        (self.status(), self.to_string()).into_response()
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Sync,
+{
+    type Rejection = ClientIpRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(client_ip) = parts.extensions.get::<Self>() {
+            return Ok(*client_ip);
+        }
+        if let Some(rejection) = parts.extensions.get::<ClientIpRejection>() {
+            return Err(rejection.clone());
+        }
+        let config = parts
+            .extensions
+            .get::<ClientIpConfig>()
+            .ok_or(ClientIpRejection::MissingSource)?;
+        Ok(Self(config.extract(&parts.headers, peer_ip(parts))?))
+    }
+}
+
+/// Extracts the client IP using a [`ClientIpConfig`] from the router state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIpFromState(pub IpAddr);
+
+impl<S> FromRequestParts<S> for ClientIpFromState
+where
+    S: Sync,
+    ClientIpConfig: FromRef<S>,
+{
+    type Rejection = ClientIpRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            ClientIpConfig::from_ref(state).extract(&parts.headers, peer_ip(parts))?,
+        ))
+    }
+}
+
+fn peer_ip(parts: &Parts) -> Option<IpAddr> {
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderName, Request, StatusCode};
+
+    use super::*;
+    use crate::{ClientIpSource, Error};
+
+    fn parts(x_real_ip: Option<&str>) -> Parts {
+        let mut builder = Request::builder();
+        if let Some(ip) = x_real_ip {
+            builder = builder.header("x-real-ip", ip);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    fn absent_header() -> Error {
+        Error::AbsentHeader {
+            header_name: HeaderName::from_static("x-real-ip"),
+        }
+    }
+
+    fn trusting_config() -> ClientIpConfig {
+        ClientIpConfig::new(ClientIpSource::XRealIp)
+            .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect())
+    }
+
+    fn connect_info(parts: &mut Parts, peer_addr: &str) {
+        parts
+            .extensions
+            .insert(ConnectInfo(peer_addr.parse::<SocketAddr>().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_client_ip() {
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+
+        assert_eq!(
+            ClientIp::from_request_parts(&mut parts(Some("1.2.3.4")), &())
+                .await
+                .unwrap_err(),
+            ClientIpRejection::MissingSource
+        );
+
+        let mut with_client_ip = parts(None);
+        with_client_ip.extensions.insert(ClientIp(ip));
+        assert_eq!(
+            ClientIp::from_request_parts(&mut with_client_ip, &())
+                .await
+                .unwrap(),
+            ClientIp(ip),
+            "inserted by the layer"
+        );
+
+        let mut with_rejection = parts(None);
+        with_rejection
+            .extensions
+            .insert(ClientIpRejection::Extraction(absent_header()));
+        assert_eq!(
+            ClientIp::from_request_parts(&mut with_rejection, &())
+                .await
+                .unwrap_err(),
+            ClientIpRejection::Extraction(absent_header()),
+            "passed through by the layer"
+        );
+
+        let mut with_config = parts(Some("1.2.3.4"));
+        with_config
+            .extensions
+            .insert(ClientIpConfig::new(ClientIpSource::XRealIp));
+        assert_eq!(
+            ClientIp::from_request_parts(&mut with_config, &())
+                .await
+                .unwrap(),
+            ClientIp(ip)
+        );
+
+        let mut with_config = parts(None);
+        with_config
+            .extensions
+            .insert(ClientIpConfig::new(ClientIpSource::XRealIp));
+        assert_eq!(
+            ClientIp::from_request_parts(&mut with_config, &())
+                .await
+                .unwrap_err(),
+            ClientIpRejection::Extraction(absent_header())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_trusted_proxies() {
+        let mut from_proxy = parts(Some("1.2.3.4"));
+        from_proxy.extensions.insert(trusting_config());
+        assert_eq!(
+            ClientIp::from_request_parts(&mut from_proxy.clone(), &())
+                .await
+                .unwrap_err(),
+            ClientIpRejection::MissingPeerAddr
+        );
+        connect_info(&mut from_proxy, "10.0.0.1:1234");
+        assert_eq!(
+            ClientIp::from_request_parts(&mut from_proxy, &())
+                .await
+                .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+
+        let mut from_client = parts(Some("1.2.3.4"));
+        from_client.extensions.insert(trusting_config());
+        connect_info(&mut from_client, "5.6.7.8:1234");
+        assert_eq!(
+            ClientIp::from_request_parts(&mut from_client, &())
+                .await
+                .unwrap(),
+            ClientIp("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_from_state() {
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        assert_eq!(
+            ClientIpFromState::from_request_parts(&mut parts(Some("1.2.3.4")), &config)
+                .await
+                .unwrap(),
+            ClientIpFromState("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(
+            ClientIpFromState::from_request_parts(&mut parts(None), &config)
+                .await
+                .unwrap_err(),
+            ClientIpRejection::Extraction(absent_header())
+        );
+
+        let mut from_client = parts(Some("1.2.3.4"));
+        connect_info(&mut from_client, "5.6.7.8:1234");
+        assert_eq!(
+            ClientIpFromState::from_request_parts(&mut from_client, &trusting_config())
+                .await
+                .unwrap(),
+            ClientIpFromState("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+    }
+
+    #[test]
+    fn test_rejection_status() {
+        assert_eq!(
+            ClientIpRejection::MissingSource.into_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            ClientIpRejection::Extraction(absent_header())
+                .into_response()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
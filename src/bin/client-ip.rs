@@ -0,0 +1,211 @@
+//! Runs client IP extractors against captured requests
+//!
+//! Headers are read from raw HTTP request heads, `curl -v` output, or plain
+//! `Name: value` lines.
+
+use std::{
+    env, fs,
+    io::{self, Read},
+    net::IpAddr,
+    process::ExitCode,
+};
+
+use client_ip::{ClientIpSource, raw::RawHeaders, topology::TopologyDetector};
+
+const USAGE: &str = "\
+Usage:
+  client-ip [--source SOURCE] [--json] [FILE]
+  client-ip detect [--json] --peer IP FILE... [--peer IP FILE...]
+
+Runs the source, or all of them, against the request read from FILE or stdin
+and prints the explained result. `detect` analyzes requests captured from the
+preceding peer and suggests sources.
+
+Input: a raw HTTP request head, `curl -v` output, or `Name: value` lines
+
+Sources, parameterized ones with the default number of hops or proxies:
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("-h" | "--help") => {
+            print!("{}", usage());
+            return ExitCode::SUCCESS;
+        }
+        Some("detect") => detect(&args[1..]),
+        _ => run(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("client-ip: {e}\n\n{}", usage());
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Explains extraction of the client IP from a single request
+fn run(args: &[String]) -> Result<(), String> {
+    let mut sources = None;
+    let mut json = false;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--source" => {
+                let name = args.next().ok_or("--source requires a value")?;
+                sources = Some(vec![
+                    name.parse::<ClientIpSource>().map_err(|e| e.to_string())?,
+                ]);
+            }
+            "--json" => json = true,
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let input = read_input(path)?;
+    let head = normalize_head(&input);
+    let headers = RawHeaders::parse(&head).map_err(|e| e.to_string())?;
+
+    let explanations: Vec<_> = sources
+        .unwrap_or_else(|| ClientIpSource::ALL.to_vec())
+        .iter()
+        .map(|source| source.explain(&headers))
+        .collect();
+
+    if json {
+        println!("{}", to_json(&explanations)?);
+    } else {
+        let texts: Vec<String> = explanations.iter().map(ToString::to_string).collect();
+        print!("{}", texts.join("\n"));
+    }
+    Ok(())
+}
+
+/// Suggests sources for requests captured from known peers
+fn detect(args: &[String]) -> Result<(), String> {
+    let mut detector = TopologyDetector::new();
+    let mut peer_ip: Option<IpAddr> = None;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--peer" => {
+                let ip = args.next().ok_or("--peer requires a value")?;
+                peer_ip = Some(ip.parse().map_err(|_| format!("invalid peer IP: {ip}"))?);
+            }
+            "--json" => json = true,
+            path => {
+                let peer_ip = peer_ip.ok_or_else(|| format!("no --peer before {path}"))?;
+                let input = read_input(Some(path))?;
+                let head = normalize_head(&input);
+                let headers = RawHeaders::parse(&head).map_err(|e| format!("{path}: {e}"))?;
+                detector.add(&headers, peer_ip);
+            }
+        }
+    }
+
+    let report = detector.report();
+    if report.samples == 0 {
+        return Err("no requests given".into());
+    }
+    if json {
+        println!("{}", to_json(&report)?);
+    } else {
+        print!("{report}");
+    }
+    Ok(())
+}
+
+fn usage() -> String {
+    let sources: String = ClientIpSource::ALL
+        .iter()
+        .map(|source| format!("  {source}\n"))
+        .collect();
+    format!("{USAGE}{sources}")
+}
+
+fn read_input(path: Option<&str>) -> Result<Vec<u8>, String> {
+    match path {
+        None | Some("-") => {
+            let mut input = Vec::new();
+            io::stdin()
+                .read_to_end(&mut input)
+                .map_err(|e| format!("can't read stdin: {e}"))?;
+            Ok(input)
+        }
+        Some(path) => fs::read(path).map_err(|e| format!("can't read {path}: {e}")),
+    }
+}
+
+/// Converts the input into a request head [`RawHeaders`] can parse
+///
+/// Only request lines (`> `) of `curl -v` output are kept, a request line is
+/// added to bare header lines, and the head is terminated.
+fn normalize_head(input: &[u8]) -> Vec<u8> {
+    let mut lines: Vec<&[u8]> = input
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+
+    if lines.iter().any(|line| line.starts_with(b"> ")) {
+        lines = lines
+            .into_iter()
+            .filter_map(|line| match line {
+                b">" => Some(&b""[..]),
+                _ => line.strip_prefix(b"> "),
+            })
+            .collect();
+    }
+
+    let first = lines.iter().position(|line| !line.is_empty());
+    let lines = &lines[first.unwrap_or(lines.len())..];
+    let has_request_line = lines.first().is_some_and(|line| {
+        line.rsplit(|&b| b == b' ')
+            .next()
+            .is_some_and(|version| version.starts_with(b"HTTP/"))
+    });
+
+    let mut head = Vec::new();
+    if !has_request_line {
+        head.extend_from_slice(b"GET / HTTP/1.1\r\n");
+    }
+    for line in lines {
+        head.extend_from_slice(line);
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_head() {
+        for input in [
+            &b"GET / HTTP/1.1\r\nX-Real-Ip: 1.1.1.1\r\n\r\nbody"[..],
+            b"X-Real-Ip: 1.1.1.1",
+            b"\nX-Real-Ip: 1.1.1.1\n",
+            b"* Connected\n> GET / HTTP/1.1\n> X-Real-Ip: 1.1.1.1\n>\n< HTTP/1.1 200 OK\n< X-Real-Ip: 6.6.6.6\n",
+        ] {
+            let head = normalize_head(input);
+            let headers = RawHeaders::parse(&head).unwrap();
+            assert_eq!(
+                client_ip::x_real_ip(&headers),
+                Ok("1.1.1.1".parse().unwrap()),
+                "{:?}",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Options of IPv6 canonicalization applied to extracted IPs
+///
+/// IPv4-mapped addresses (`::ffff:1.2.3.4`) are always converted to IPv4.
+/// Unwrapping IPv4 addresses embedded by transition mechanisms is opt-in, as
+/// the resulting address identifies the translator or relay endpoint rather
+/// than an IPv6 host:
+///
+/// ```
+/// use client_ip::{Canonicalization, Provenance};
+///
+/// let canonicalization = Canonicalization {
+///     nat64: true,
+///     ..Default::default()
+/// };
+/// let canonical = canonicalization.canonicalize("64:ff9b::102:304".parse().unwrap());
+/// assert_eq!(canonical.ip, "1.2.3.4".parse::<std::net::IpAddr>().unwrap());
+/// assert!(matches!(canonical.provenance, Provenance::Nat64(_)));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Canonicalization {
+    /// Unwrap NAT64 well-known prefix addresses (`64:ff9b::/96`, RFC 6052)
+    pub nat64: bool,
+    /// Unwrap 6to4 addresses (`2002::/16`, RFC 3056)
+    pub six_to_four: bool,
+    /// Unwrap client addresses of Teredo tunnels (`2001::/32`, RFC 4380)
+    pub teredo: bool,
+}
+
+/// An IP after [`Canonicalization`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanonicalIp {
+    /// The canonical IP
+    pub ip: IpAddr,
+    /// Where the IP comes from
+    pub provenance: Provenance,
+}
+
+/// Where a [`CanonicalIp`] comes from, carrying the original IPv6 address
+/// for unwrapped ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    /// The IP was already canonical
+    AsIs,
+    /// Unwrapped from an IPv4-mapped address
+    Ipv4Mapped(Ipv6Addr),
+    /// Unwrapped from a NAT64 address
+    Nat64(Ipv6Addr),
+    /// Unwrapped from a 6to4 address
+    SixToFour(Ipv6Addr),
+    /// Unwrapped from a Teredo address
+    Teredo(Ipv6Addr),
+}
+
+impl Canonicalization {
+    /// Canonicalizes an IP, chain it after any extractor:
+    ///
+    /// ```
+    /// # let header_map = http::HeaderMap::new();
+    /// let canonicalization = client_ip::Canonicalization::default();
+    /// let ip = client_ip::x_real_ip(&header_map).map(|ip| canonicalization.canonicalize(ip).ip);
+    /// ```
+    pub fn canonicalize(&self, ip: IpAddr) -> CanonicalIp {
+        let IpAddr::V6(ipv6) = ip else {
+            return CanonicalIp {
+                ip,
+                provenance: Provenance::AsIs,
+            };
+        };
+
+        let segments = ipv6.segments();
+        let (ipv4, provenance) = if let Some(ipv4) = ipv6.to_ipv4_mapped() {
+            (ipv4, Provenance::Ipv4Mapped(ipv6))
+        } else if self.nat64 && matches!(segments, [0x64, 0xff9b, 0, 0, 0, 0, ..]) {
+            (
+                ipv4_from_segments(segments[6], segments[7]),
+                Provenance::Nat64(ipv6),
+            )
+        } else if self.six_to_four && segments[0] == 0x2002 {
+            (
+                ipv4_from_segments(segments[1], segments[2]),
+                Provenance::SixToFour(ipv6),
+            )
+        } else if self.teredo && segments[0] == 0x2001 && segments[1] == 0 {
+            // The client address is stored with all bits inverted
+            (
+                ipv4_from_segments(!segments[6], !segments[7]),
+                Provenance::Teredo(ipv6),
+            )
+        } else {
+            return CanonicalIp {
+                ip,
+                provenance: Provenance::AsIs,
+            };
+        };
+
+        CanonicalIp {
+            ip: ipv4.into(),
+            provenance,
+        }
+    }
+}
+
+fn ipv4_from_segments(high: u16, low: u16) -> Ipv4Addr {
+    Ipv4Addr::from((u32::from(high) << 16) | u32::from(low))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: Canonicalization = Canonicalization {
+        nat64: true,
+        six_to_four: true,
+        teredo: true,
+    };
+
+    fn canonicalize(canonicalization: Canonicalization, ip: &str) -> (IpAddr, Provenance) {
+        let canonical = canonicalization.canonicalize(ip.parse().unwrap());
+        (canonical.ip, canonical.provenance)
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        for ip in ["1.2.3.4", "2606:4700::1111", "::1", "::1.2.3.4"] {
+            assert_eq!(
+                canonicalize(ALL, ip),
+                (ip.parse().unwrap(), Provenance::AsIs),
+                "{ip}"
+            );
+        }
+
+        assert_eq!(
+            canonicalize(Canonicalization::default(), "::ffff:1.2.3.4"),
+            (
+                "1.2.3.4".parse().unwrap(),
+                Provenance::Ipv4Mapped("::ffff:1.2.3.4".parse().unwrap())
+            )
+        );
+
+        for ip in [
+            "64:ff9b::1.2.3.4",
+            "2002:102:304::1",
+            "2001:0:4136:e378:8000:63bf:fefd:fcfb",
+        ] {
+            assert_eq!(
+                canonicalize(Canonicalization::default(), ip),
+                (ip.parse().unwrap(), Provenance::AsIs),
+                "disabled {ip}"
+            );
+        }
+
+        assert_eq!(
+            canonicalize(ALL, "64:ff9b::1.2.3.4"),
+            (
+                "1.2.3.4".parse().unwrap(),
+                Provenance::Nat64("64:ff9b::1.2.3.4".parse().unwrap())
+            )
+        );
+        assert_eq!(
+            canonicalize(ALL, "64:ff9b:1::1.2.3.4").1,
+            Provenance::AsIs,
+            "local-use NAT64 prefix"
+        );
+        assert_eq!(
+            canonicalize(ALL, "2002:102:304::1"),
+            (
+                "1.2.3.4".parse().unwrap(),
+                Provenance::SixToFour("2002:102:304::1".parse().unwrap())
+            )
+        );
+        // Example from RFC 4380, section 4
+        assert_eq!(
+            canonicalize(ALL, "2001:0:4136:e378:8000:63bf:3fff:fdd2"),
+            (
+                "192.0.2.45".parse().unwrap(),
+                Provenance::Teredo("2001:0:4136:e378:8000:63bf:3fff:fdd2".parse().unwrap())
+            )
+        );
+    }
+}
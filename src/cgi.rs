@@ -0,0 +1,139 @@
+//! CGI and FastCGI request meta-variables
+//!
+//! Web servers pass request headers to CGI and FastCGI applications as
+//! `HTTP_*` meta-variables, e.g. `X-Forwarded-For` becomes
+//! `HTTP_X_FORWARDED_FOR`, and the peer address as `REMOTE_ADDR`. [`CgiEnv`]
+//! implements [`HeaderAccess`] over them, so any extractor runs on it:
+//!
+//! ```
+//! use client_ip::{ClientIpSource, TrustedProxies, cgi::CgiEnv};
+//!
+//! let env: CgiEnv = [
+//!     ("REMOTE_ADDR", "10.0.0.1"),
+//!     ("HTTP_X_FORWARDED_FOR", "1.1.1.1, 2.2.2.2"),
+//! ]
+//! .into_iter()
+//! .collect();
+//!
+//! let trusted_proxies: TrustedProxies = ["10.0.0.0/8".parse().unwrap()].into_iter().collect();
+//! let ip = trusted_proxies.extract(
+//!     &ClientIpSource::RightmostXForwardedFor,
+//!     &env,
+//!     env.remote_addr().unwrap(),
+//! );
+//! assert_eq!(ip, Ok("2.2.2.2".parse().unwrap()));
+//! ```
+
+use std::net::IpAddr;
+
+use http::HeaderName;
+
+use crate::HeaderAccess;
+
+/// Meta-variables of a CGI or FastCGI request
+#[derive(Debug, Clone, Default)]
+pub struct CgiEnv {
+    vars: Vec<(String, String)>,
+}
+
+impl CgiEnv {
+    /// Collects meta-variables from the environment of the current process,
+    /// variables with non-UTF-8 names or values are skipped
+    pub fn from_env() -> Self {
+        std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect()
+    }
+
+    /// Returns value of the meta-variable
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(var_name, _)| var_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the peer IP from the `REMOTE_ADDR` meta-variable
+    pub fn remote_addr(&self) -> Option<IpAddr> {
+        self.get("REMOTE_ADDR")?.trim().parse().ok()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for CgiEnv {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            vars: iter
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+impl HeaderAccess for CgiEnv {
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+        let var_name = meta_variable_name(header_name);
+        self.vars
+            .iter()
+            .filter(move |(name, _)| *name == var_name)
+            .map(|(_, value)| value.as_bytes())
+    }
+}
+
+/// `X-Forwarded-For` -> `HTTP_X_FORWARDED_FOR`
+fn meta_variable_name(header_name: &HeaderName) -> String {
+    let header_name = header_name.as_str();
+    let mut var_name = String::with_capacity(header_name.len() + 5);
+    var_name.push_str("HTTP_");
+    var_name.extend(header_name.chars().map(|c| match c {
+        '-' => '_',
+        c => c.to_ascii_uppercase(),
+    }));
+    var_name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, cf_connecting_ip, rightmost_x_forwarded_for};
+
+    #[test]
+    fn test_meta_variable_name() {
+        assert_eq!(
+            meta_variable_name(&HeaderName::from_static("x-forwarded-for")),
+            "HTTP_X_FORWARDED_FOR"
+        );
+        assert_eq!(
+            meta_variable_name(&HeaderName::from_static("cf-connecting-ip")),
+            "HTTP_CF_CONNECTING_IP"
+        );
+    }
+
+    #[test]
+    fn test_cgi_env() {
+        let env: CgiEnv = [
+            ("REMOTE_ADDR", " 10.0.0.1 "),
+            ("HTTP_X_FORWARDED_FOR", "1.1.1.1, 2.2.2.2"),
+            ("HTTP_CF_CONNECTING_IP", "3.3.3.3"),
+            ("X_FORWARDED_FOR", "4.4.4.4"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(env.remote_addr(), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(
+            rightmost_x_forwarded_for(&env),
+            Ok("2.2.2.2".parse().unwrap())
+        );
+        assert_eq!(cf_connecting_ip(&env), Ok("3.3.3.3".parse().unwrap()));
+
+        let env = CgiEnv::default();
+        assert_eq!(env.remote_addr(), None);
+        assert_eq!(
+            rightmost_x_forwarded_for(&env),
+            Err(Error::AbsentHeader {
+                header_name: HeaderName::from_static("x-forwarded-for")
+            })
+        );
+    }
+}
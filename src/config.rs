@@ -0,0 +1,197 @@
+use std::net::IpAddr;
+
+use crate::{
+    Canonicalization, ClientIpRejection, ClientIpSource, HeaderAccess, Result, TrustedProxies,
+    explain::{Explanation, Extractor, Recorder, Tracer},
+};
+
+/// Configuration of framework integrations resolving the client IP of a
+/// request
+///
+/// The source headers are trusted for every request or, when
+/// [`TrustedProxies`] are set, only for requests whose peer is a trusted
+/// proxy, the peer IP is the client IP otherwise. The client IP is
+/// canonicalized, by default only IPv4-mapped addresses are converted to IPv4.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIpConfig {
+    source: ClientIpSource,
+    trusted_proxies: Option<TrustedProxies>,
+    canonicalization: Canonicalization,
+}
+
+impl ClientIpConfig {
+    /// Creates a configuration trusting the source headers of every request
+    pub fn new(source: ClientIpSource) -> Self {
+        Self {
+            source,
+            trusted_proxies: None,
+            canonicalization: Canonicalization::default(),
+        }
+    }
+
+    /// Trusts the source headers only for requests coming from the proxies,
+    /// the peer IP is used as the client IP otherwise
+    pub fn trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(trusted_proxies);
+        self
+    }
+
+    /// Sets the canonicalization applied to the client IP
+    pub fn canonicalization(mut self, canonicalization: Canonicalization) -> Self {
+        self.canonicalization = canonicalization;
+        self
+    }
+
+    /// Returns the source of the client IP
+    pub fn source(&self) -> ClientIpSource {
+        self.source
+    }
+
+    /// Extracts the client IP of a request
+    ///
+    /// The `peer_ip` is the IP of the connection the request came from, it's
+    /// only required with [trusted proxies](Self::trusted_proxies) set.
+    pub fn extract(
+        &self,
+        header_map: &impl HeaderAccess,
+        peer_ip: Option<IpAddr>,
+    ) -> std::result::Result<IpAddr, ClientIpRejection> {
+        let result = self
+            .extract_traced(header_map, peer_ip, &mut ())
+            .ok_or(ClientIpRejection::MissingPeerAddr)?;
+        Ok(result?)
+    }
+
+    #[cfg(feature = "hyper")]
+    /// Extracts the client IP of a request with a known peer
+    pub(crate) fn extract_with_peer(
+        &self,
+        header_map: &impl HeaderAccess,
+        peer_ip: IpAddr,
+    ) -> Result<IpAddr> {
+        self.extract_traced(header_map, Some(peer_ip), &mut ())
+            .expect("the peer IP is given")
+    }
+
+    /// Traces extraction of the client IP, returns `None` if the peer IP is
+    /// required but missing
+    pub fn explain(
+        &self,
+        header_map: &impl HeaderAccess,
+        peer_ip: Option<IpAddr>,
+    ) -> Option<Explanation> {
+        let mut recorder = Recorder::default();
+        let result = self.extract_traced(header_map, peer_ip, &mut recorder)?;
+        Some(recorder.finish(Extractor::Source(self.source), result))
+    }
+
+    /// Returns `None` if the peer IP is required but missing
+    fn extract_traced(
+        &self,
+        header_map: &impl HeaderAccess,
+        peer_ip: Option<IpAddr>,
+        tracer: &mut impl Tracer,
+    ) -> Option<Result<IpAddr>> {
+        let result = match (&self.trusted_proxies, peer_ip) {
+            (None, _) => self.source.extract_traced(header_map, tracer),
+            (Some(trusted_proxies), Some(peer_ip)) => {
+                trusted_proxies.extract_traced(&self.source, header_map, peer_ip, tracer)
+            }
+            (Some(_), None) => return None,
+        };
+        Some(result.map(|ip| self.canonicalization.canonicalize(ip).ip))
+    }
+}
+
+impl From<ClientIpSource> for ClientIpConfig {
+    fn from(source: ClientIpSource) -> Self {
+        Self::new(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_extract() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "1.2.3.4".parse().unwrap());
+        let proxy_ip = Some("10.0.0.1".parse().unwrap());
+
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        assert_eq!(
+            config.extract(&headers, None),
+            Ok("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(
+            config.extract(&HeaderMap::new(), proxy_ip),
+            Err(ClientIpRejection::Extraction(Error::AbsentHeader {
+                header_name: http::HeaderName::from_static("x-real-ip")
+            }))
+        );
+
+        let config = config.trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+        assert_eq!(
+            config.extract(&headers, None),
+            Err(ClientIpRejection::MissingPeerAddr)
+        );
+        assert_eq!(
+            config.extract(&headers, proxy_ip),
+            Ok("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+        assert_eq!(
+            config.extract(&headers, Some("5.6.7.8".parse().unwrap())),
+            Ok("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+        assert_eq!(
+            config.extract(&headers, Some("::ffff:5.6.7.8".parse().unwrap())),
+            Ok("5.6.7.8".parse().unwrap()),
+            "IPv4-mapped peer"
+        );
+        assert!(
+            config
+                .explain(&headers, proxy_ip)
+                .unwrap()
+                .peer
+                .unwrap()
+                .trusted
+        );
+        let explanation = config
+            .explain(&headers, Some("5.6.7.8".parse().unwrap()))
+            .unwrap();
+        assert!(explanation.headers.is_empty());
+        assert_eq!(explanation.result, Ok("5.6.7.8".parse().unwrap()));
+        assert_eq!(config.explain(&headers, None), None);
+    }
+
+    #[test]
+    fn test_canonicalization() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "::ffff:1.2.3.4".parse().unwrap());
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        assert_eq!(
+            config.extract(&headers, None),
+            Ok("1.2.3.4".parse().unwrap())
+        );
+
+        headers.insert("x-real-ip", "64:ff9b::102:304".parse().unwrap());
+        assert_eq!(
+            config.extract(&headers, None),
+            Ok("64:ff9b::102:304".parse().unwrap())
+        );
+        let config = config.canonicalization(Canonicalization {
+            nat64: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            config.extract(&headers, None),
+            Ok("1.2.3.4".parse().unwrap())
+        );
+    }
+}
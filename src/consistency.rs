@@ -0,0 +1,250 @@
+//! Detecting conflicts between IP headers
+//!
+//! A request carrying several IP headers with disagreeing values, e.g.
+//! `X-Real-Ip` and `X-Forwarded-For`, signals spoofing or a misconfigured
+//! proxy. [`ConsistencyChecker`] runs pairs of extractors over the same
+//! headers and compares the results, a header present for only one source of
+//! a pair or failing extraction fails the check as well:
+//!
+//! ```
+//! use client_ip::{
+//!     ClientIpSource,
+//!     consistency::{Consistency, ConsistencyChecker},
+//! };
+//! use http::HeaderMap;
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("x-real-ip", "1.2.3.4".parse().unwrap());
+//! headers.insert("x-forwarded-for", "6.6.6.6".parse().unwrap());
+//!
+//! let checker = ConsistencyChecker::new()
+//!     .pair(ClientIpSource::XRealIp, ClientIpSource::RightmostXForwardedFor)
+//!     .pair(ClientIpSource::CfConnectingIp, ClientIpSource::TrueClientIp);
+//!
+//! let reports = checker.check(&headers);
+//! assert_eq!(
+//!     reports[0].consistency,
+//!     Consistency::Disagree("1.2.3.4".parse().unwrap(), "6.6.6.6".parse().unwrap())
+//! );
+//! assert_eq!(reports[1].consistency, Consistency::Absent);
+//!
+//! headers.insert("x-forwarded-for", "1.2.3.4".parse().unwrap());
+//! assert_eq!(checker.require_consistent(&headers), Ok(()));
+//! headers.remove("x-forwarded-for");
+//! assert!(checker.require_consistent(&headers).is_err());
+//! ```
+
+use std::net::IpAddr;
+
+use crate::{ClientIpSource, Error, HeaderAccess, Result};
+
+/// Runs pairs of extractors over the same headers and compares their results
+#[derive(Debug, Clone, Default)]
+pub struct ConsistencyChecker {
+    pairs: Vec<(ClientIpSource, ClientIpSource)>,
+}
+
+/// Comparison of a pair of sources for a single request
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairReport {
+    /// The compared sources
+    pub sources: (ClientIpSource, ClientIpSource),
+    /// Outcome of the comparison
+    pub consistency: Consistency,
+}
+
+/// Outcome of comparing IPs extracted by two sources
+#[derive(Debug, Clone, PartialEq)]
+pub enum Consistency {
+    /// Both sources yield the same IP
+    Agree(IpAddr),
+    /// The sources yield different IPs, in order of the pair
+    Disagree(IpAddr, IpAddr),
+    /// Only the left source yields an IP, the header of the right one is
+    /// absent
+    LeftOnly(IpAddr),
+    /// Only the right source yields an IP, the header of the left one is
+    /// absent
+    RightOnly(IpAddr),
+    /// Headers of both sources are absent
+    Absent,
+    /// A source fails for another reason than an absent header, e.g. a
+    /// malformed or repeated header, the error of the left source comes first
+    Failed(Error),
+}
+
+impl ConsistencyChecker {
+    /// Creates a checker without pairs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pair of sources to compare
+    pub fn pair(mut self, left: ClientIpSource, right: ClientIpSource) -> Self {
+        self.pairs.push((left, right));
+        self
+    }
+
+    /// Compares each pair of sources, returns reports in order of the pairs
+    pub fn check(&self, header_map: &impl HeaderAccess) -> Vec<PairReport> {
+        self.pairs
+            .iter()
+            .map(|&(left, right)| PairReport {
+                sources: (left, right),
+                consistency: match (left.extract(header_map), right.extract(header_map)) {
+                    (Ok(left_ip), Ok(right_ip)) if left_ip == right_ip => {
+                        Consistency::Agree(left_ip)
+                    }
+                    (Ok(left_ip), Ok(right_ip)) => Consistency::Disagree(left_ip, right_ip),
+                    (Err(error), _) | (_, Err(error)) if !is_absent(&error) => {
+                        Consistency::Failed(error)
+                    }
+                    (Ok(left_ip), Err(_)) => Consistency::LeftOnly(left_ip),
+                    (Err(_), Ok(right_ip)) => Consistency::RightOnly(right_ip),
+                    (Err(_), Err(_)) => Consistency::Absent,
+                },
+            })
+            .collect()
+    }
+
+    /// Fails on the first pair of sources not yielding the same IP
+    ///
+    /// Only pairs with headers of both sources absent pass without an IP.
+    pub fn require_consistent(&self, header_map: &impl HeaderAccess) -> Result<()> {
+        for PairReport {
+            sources: (left, right),
+            consistency,
+        } in self.check(header_map)
+        {
+            match consistency {
+                Consistency::Agree(_) | Consistency::Absent => {}
+                Consistency::Disagree(left_ip, right_ip) => {
+                    return Err(Error::InconsistentSources {
+                        sources: (left, right),
+                        ips: (left_ip, right_ip),
+                    });
+                }
+                Consistency::LeftOnly(_) => {
+                    return Err(Error::UnpairedSource {
+                        source: left,
+                        absent: right,
+                    });
+                }
+                Consistency::RightOnly(_) => {
+                    return Err(Error::UnpairedSource {
+                        source: right,
+                        absent: left,
+                    });
+                }
+                Consistency::Failed(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_absent(error: &Error) -> bool {
+    matches!(error, Error::AbsentHeader { .. })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+    use crate::header_names::{FLY_CLIENT_IP, X_REAL_IP};
+
+    #[test]
+    fn test_check() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "1.2.3.4".parse().unwrap());
+        headers.insert("x-forwarded-for", "6.6.6.6, 1.2.3.4".parse().unwrap());
+        headers.insert("cf-connecting-ip", "1.2.3.4".parse().unwrap());
+        headers.insert("true-client-ip", "5.5.5.5".parse().unwrap());
+        headers.insert("fly-client-ip", "foo".parse().unwrap());
+
+        let checker = ConsistencyChecker::new()
+            .pair(
+                ClientIpSource::XRealIp,
+                ClientIpSource::RightmostXForwardedFor,
+            )
+            .pair(ClientIpSource::CfConnectingIp, ClientIpSource::TrueClientIp)
+            .pair(ClientIpSource::XRealIp, ClientIpSource::FlyClientIp)
+            .pair(
+                ClientIpSource::XRealIp,
+                ClientIpSource::XEnvoyExternalAddress,
+            )
+            .pair(
+                ClientIpSource::XEnvoyExternalAddress,
+                ClientIpSource::TrueClientIp,
+            )
+            .pair(
+                ClientIpSource::XEnvoyExternalAddress,
+                ClientIpSource::IngressNginx { trusted_proxies: 1 },
+            );
+
+        assert_eq!(
+            checker
+                .check(&headers)
+                .into_iter()
+                .map(|report| report.consistency)
+                .collect::<Vec<_>>(),
+            [
+                Consistency::Agree("1.2.3.4".parse().unwrap()),
+                Consistency::Disagree("1.2.3.4".parse().unwrap(), "5.5.5.5".parse().unwrap()),
+                Consistency::Failed(Error::MalformedHeaderValue {
+                    header_name: FLY_CLIENT_IP,
+                    header_value: "foo".to_owned(),
+                }),
+                Consistency::LeftOnly("1.2.3.4".parse().unwrap()),
+                Consistency::RightOnly("5.5.5.5".parse().unwrap()),
+                Consistency::Absent,
+            ]
+        );
+        assert_eq!(
+            checker.require_consistent(&headers),
+            Err(Error::InconsistentSources {
+                sources: (ClientIpSource::CfConnectingIp, ClientIpSource::TrueClientIp),
+                ips: ("1.2.3.4".parse().unwrap(), "5.5.5.5".parse().unwrap()),
+            })
+        );
+
+        headers.insert("true-client-ip", "1.2.3.4".parse().unwrap());
+        assert_eq!(
+            checker.require_consistent(&headers),
+            Err(Error::MalformedHeaderValue {
+                header_name: FLY_CLIENT_IP,
+                header_value: "foo".to_owned(),
+            })
+        );
+
+        headers.insert("fly-client-ip", "1.2.3.4".parse().unwrap());
+        assert_eq!(
+            checker.require_consistent(&headers),
+            Err(Error::UnpairedSource {
+                source: ClientIpSource::XRealIp,
+                absent: ClientIpSource::XEnvoyExternalAddress,
+            })
+        );
+
+        headers.insert("x-envoy-external-address", "1.2.3.4".parse().unwrap());
+        assert_eq!(
+            checker.require_consistent(&headers),
+            Err(Error::UnpairedSource {
+                source: ClientIpSource::XEnvoyExternalAddress,
+                absent: ClientIpSource::IngressNginx { trusted_proxies: 1 },
+            })
+        );
+
+        headers.insert("x-original-forwarded-for", "1.2.3.4".parse().unwrap());
+        assert_eq!(checker.require_consistent(&headers), Ok(()));
+
+        headers.append("x-real-ip", "1.2.3.4".parse().unwrap());
+        assert_eq!(
+            checker.require_consistent(&headers),
+            Err(Error::SingleHeaderRequired {
+                header_name: X_REAL_IP
+            })
+        );
+    }
+}
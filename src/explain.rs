@@ -0,0 +1,636 @@
+//! Step-by-step traces of extraction decisions
+//!
+//! [`ClientIpSource::explain`] runs an extractor and records which header
+//! lines it read, which of them it selected, and how it treated each hop of
+//! list headers. The trace renders as text with [`Display`](fmt::Display) and
+//! as JSON with the `serde` feature:
+//!
+//! ```
+//! use client_ip::ClientIpSource;
+//! use http::HeaderMap;
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+//!
+//! let explanation = ClientIpSource::Traefik { trusted_proxies: 1 }.explain(&headers);
+//! assert_eq!(explanation.result, Ok("1.1.1.1".parse().unwrap()));
+//! assert_eq!(
+//!     explanation.to_string(),
+//!     "\
+//! source: traefik:1
+//! header x-forwarded-for, all lines used:
+//!   [0] 1.1.1.1, 2.2.2.2 (selected)
+//! hops of x-forwarded-for from the right:
+//!   2.2.2.2: trusted proxy, skipped
+//!   1.1.1.1: selected
+//! result: 1.1.1.1
+//! "
+//! );
+//! ```
+
+use std::{fmt, net::IpAddr};
+
+use http::HeaderName;
+
+#[cfg(feature = "forwarded-header")]
+use crate::forwarding::{ForwardedNode, ForwardedNodeResolver};
+use crate::{ClientIpSource, HeaderAccess, Result};
+
+/// Trace of an extraction decision, see [`ClientIpSource::explain`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Explanation {
+    /// The explained extractor
+    pub extractor: Extractor,
+    /// Decision on the peer, present for extraction behind
+    /// [`TrustedProxies`](crate::TrustedProxies)
+    pub peer: Option<PeerDecision>,
+    /// Headers the extractor read, in order of reading
+    pub headers: Vec<HeaderRead>,
+    /// Name of the list header the hops belong to
+    pub hops_header_name: Option<String>,
+    /// Hops of the list header in order of consideration, i.e. from the
+    /// right, or from the left for
+    /// [`Extractor::LeftmostNonPrivateXForwardedFor`]
+    pub hops: Vec<Hop>,
+    /// The decision of the extractor
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_result"))]
+    pub result: Result<IpAddr>,
+}
+
+/// An explained extractor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Extractor {
+    /// The extractor of a [`ClientIpSource`]
+    Source(ClientIpSource),
+    #[cfg(feature = "forwarded-header")]
+    /// [`rightmost_forwarded_by`](crate::rightmost_forwarded_by)
+    RightmostForwardedBy,
+    #[cfg(feature = "forwarded-header")]
+    /// [`rightmost_forwarded_resolved`](crate::rightmost_forwarded_resolved)
+    RightmostForwardedResolved,
+    /// [`spoofable::leftmost_non_private_x_forwarded_for`](crate::spoofable::leftmost_non_private_x_forwarded_for)
+    LeftmostNonPrivateXForwardedFor,
+}
+
+/// Whether the headers are trusted for the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PeerDecision {
+    /// IP of the peer
+    pub ip: IpAddr,
+    /// The peer is a trusted proxy, so the headers are used, otherwise the
+    /// peer IP is the client IP
+    pub trusted: bool,
+}
+
+/// Lines of a header read by an extractor
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HeaderRead {
+    /// Header name
+    pub header_name: String,
+    /// How the extractor selects the lines
+    pub selection: Selection,
+    /// All the lines of the header in order of appearance, non-UTF-8 bytes
+    /// are replaced
+    pub lines: Vec<String>,
+    /// Indexes of the lines the extractor used
+    pub selected: Vec<usize>,
+}
+
+/// How an extractor selects lines of a header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Selection {
+    /// The header must occur only once
+    Single,
+    /// The last line is used
+    Last,
+    /// All the lines are joined into a single list
+    All,
+}
+
+/// An entry of a list header considered by an extractor
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Hop {
+    /// The entry, `Forwarded` elements are normalized
+    pub entry: String,
+    /// How the extractor treated the entry
+    pub verdict: Verdict,
+}
+
+/// How an extractor treated a hop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Verdict {
+    /// Added by a trusted proxy, skipped
+    Trusted,
+    /// Taken as the client
+    Selected,
+    /// Beyond the client, so controlled by the client and ignored
+    Ignored,
+    /// Taken as the client, but isn't an IP, failing the extraction
+    Malformed,
+    /// Taken as the client, but the obfuscated or `unknown` node isn't
+    /// resolved, failing the extraction
+    Unresolved,
+    /// The `Forwarded` element isn't added by a known proxy, failing the
+    /// extraction
+    UnknownBy,
+    /// Isn't an IP, skipped
+    SkippedMalformed,
+    /// Isn't a globally routable IP, skipped
+    SkippedNonGlobal,
+}
+
+impl Verdict {
+    /// Verdict on the hop taken as the client
+    pub(crate) fn of(result: &Result<IpAddr>) -> Self {
+        match result {
+            Ok(_) => Self::Selected,
+            #[cfg(feature = "forwarded-header")]
+            Err(
+                crate::Error::ForwardedObfuscated { .. } | crate::Error::ForwardedUnknown { .. },
+            ) => Self::Unresolved,
+            #[cfg(feature = "forwarded-header")]
+            Err(crate::Error::ForwardedUnknownBy { .. }) => Self::UnknownBy,
+            Err(_) => Self::Malformed,
+        }
+    }
+}
+
+/// Receives decisions of extractors, `()` ignores them
+pub(crate) trait Tracer {
+    /// The header is about to be read
+    fn header(
+        &mut self,
+        _header_map: &impl HeaderAccess,
+        _header_name: &HeaderName,
+        _selection: Selection,
+    ) {
+    }
+
+    /// The peer is checked against trusted proxies
+    fn peer(&mut self, _ip: IpAddr, _trusted: bool) {}
+
+    /// Hops of the list header follow
+    fn hops_of(&mut self, _header_name: &HeaderName) {}
+
+    /// A hop of the list header is considered
+    fn hop(&mut self, _entry: impl fmt::Display, _verdict: Verdict) {}
+}
+
+impl Tracer for () {}
+
+/// A [`Tracer`] collecting decisions into an [`Explanation`]
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    peer: Option<PeerDecision>,
+    headers: Vec<HeaderRead>,
+    hops_header_name: Option<String>,
+    hops: Vec<Hop>,
+}
+
+impl Recorder {
+    pub(crate) fn finish(self, extractor: Extractor, result: Result<IpAddr>) -> Explanation {
+        Explanation {
+            extractor,
+            peer: self.peer,
+            headers: self.headers,
+            hops_header_name: self.hops_header_name,
+            hops: self.hops,
+            result,
+        }
+    }
+}
+
+impl Tracer for Recorder {
+    fn header(
+        &mut self,
+        header_map: &impl HeaderAccess,
+        header_name: &HeaderName,
+        selection: Selection,
+    ) {
+        let lines: Vec<String> = header_map
+            .header_values(header_name)
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+            .collect();
+        let selected = match selection {
+            Selection::Single if lines.len() == 1 => vec![0],
+            Selection::Single => Vec::new(),
+            Selection::Last => lines.len().checked_sub(1).into_iter().collect(),
+            Selection::All => (0..lines.len()).collect(),
+        };
+        self.headers.push(HeaderRead {
+            header_name: header_name.as_str().to_owned(),
+            selection,
+            lines,
+            selected,
+        });
+    }
+
+    fn peer(&mut self, ip: IpAddr, trusted: bool) {
+        self.peer = Some(PeerDecision { ip, trusted });
+    }
+
+    fn hops_of(&mut self, header_name: &HeaderName) {
+        self.hops_header_name = Some(header_name.as_str().to_owned());
+    }
+
+    fn hop(&mut self, entry: impl fmt::Display, verdict: Verdict) {
+        self.hops.push(Hop {
+            entry: entry.to_string(),
+            verdict,
+        });
+    }
+}
+
+#[cfg(feature = "forwarded-header")]
+/// Traces [`rightmost_forwarded_by`](crate::rightmost_forwarded_by)
+pub fn rightmost_forwarded_by(
+    header_map: &impl HeaderAccess,
+    proxies: &[ForwardedNode],
+) -> Explanation {
+    let mut recorder = Recorder::default();
+    let result = crate::rightmost_forwarded_by_traced(header_map, proxies, &mut recorder);
+    recorder.finish(Extractor::RightmostForwardedBy, result)
+}
+
+#[cfg(feature = "forwarded-header")]
+/// Traces [`rightmost_forwarded_resolved`](crate::rightmost_forwarded_resolved)
+pub fn rightmost_forwarded_resolved(
+    header_map: &impl HeaderAccess,
+    resolver: &impl ForwardedNodeResolver,
+) -> Explanation {
+    let mut recorder = Recorder::default();
+    let result = crate::rightmost_forwarded_resolved_traced(header_map, resolver, &mut recorder);
+    recorder.finish(Extractor::RightmostForwardedResolved, result)
+}
+
+/// Traces
+/// [`spoofable::leftmost_non_private_x_forwarded_for`](crate::spoofable::leftmost_non_private_x_forwarded_for)
+pub fn leftmost_non_private_x_forwarded_for(header_map: &impl HeaderAccess) -> Explanation {
+    let mut recorder = Recorder::default();
+    let result =
+        crate::spoofable::leftmost_non_private_x_forwarded_for_traced(header_map, &mut recorder);
+    recorder.finish(Extractor::LeftmostNonPrivateXForwardedFor, result)
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let from_left = self.extractor == Extractor::LeftmostNonPrivateXForwardedFor;
+        match self.extractor {
+            Extractor::Source(source) => writeln!(f, "source: {source}")?,
+            #[cfg(feature = "forwarded-header")]
+            Extractor::RightmostForwardedBy => writeln!(f, "extractor: rightmost_forwarded_by")?,
+            #[cfg(feature = "forwarded-header")]
+            Extractor::RightmostForwardedResolved => {
+                writeln!(f, "extractor: rightmost_forwarded_resolved")?
+            }
+            Extractor::LeftmostNonPrivateXForwardedFor => {
+                writeln!(f, "extractor: leftmost_non_private_x_forwarded_for")?
+            }
+        }
+        if let Some(peer) = &self.peer {
+            match peer.trusted {
+                true => writeln!(f, "peer {}: trusted proxy, headers used", peer.ip)?,
+                false => writeln!(
+                    f,
+                    "peer {}: not a trusted proxy, taken as the client",
+                    peer.ip
+                )?,
+            }
+        }
+        for read in &self.headers {
+            let selection = match read.selection {
+                Selection::Single => "must occur once",
+                Selection::Last => "last line used",
+                Selection::All => "all lines used",
+            };
+            write!(f, "header {}, {selection}:", read.header_name)?;
+            if read.lines.is_empty() {
+                writeln!(f, " absent")?;
+                continue;
+            }
+            writeln!(f)?;
+            for (i, line) in read.lines.iter().enumerate() {
+                write!(f, "  [{i}] {line}")?;
+                if read.selected.contains(&i) {
+                    write!(f, " (selected)")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        if let Some(header_name) = self
+            .hops_header_name
+            .as_ref()
+            .filter(|_| !self.hops.is_empty())
+        {
+            let direction = if from_left { "left" } else { "right" };
+            writeln!(f, "hops of {header_name} from the {direction}:")?;
+            for hop in &self.hops {
+                let verdict = match hop.verdict {
+                    Verdict::Trusted => "trusted proxy, skipped",
+                    Verdict::Selected => "selected",
+                    Verdict::Ignored if from_left => "right of the client, ignored",
+                    Verdict::Ignored => "left of the client, ignored",
+                    Verdict::Malformed => "not an IP, fails the extraction",
+                    Verdict::Unresolved => "unresolved identifier, fails the extraction",
+                    Verdict::UnknownBy => "not added by a known proxy, fails the extraction",
+                    Verdict::SkippedMalformed => "not an IP, skipped",
+                    Verdict::SkippedNonGlobal => "not globally routable, skipped",
+                };
+                writeln!(f, "  {}: {verdict}", hop.entry)?;
+            }
+        }
+        match &self.result {
+            Ok(ip) => writeln!(f, "result: {ip}"),
+            Err(e) => writeln!(f, "error: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_result<S: serde::Serializer>(
+    result: &Result<IpAddr>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Outcome<'a> {
+        Ip(&'a IpAddr),
+        Error(String),
+    }
+
+    match result {
+        Ok(ip) => Outcome::Ip(ip),
+        Err(e) => Outcome::Error(e.to_string()),
+    }
+    .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+    use crate::{Error, header_names::X_REAL_IP};
+
+    #[test]
+    fn test_explain() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-forwarded-for", "6.6.6.6, 1.1.1.1".parse().unwrap());
+        headers.append("x-forwarded-for", "2.2.2.2".parse().unwrap());
+        headers.append("x-real-ip", "1.1.1.1".parse().unwrap());
+        headers.append("x-real-ip", "2.2.2.2".parse().unwrap());
+
+        let explanation = ClientIpSource::Envoy {
+            xff_num_trusted_hops: 1,
+        }
+        .explain(&headers);
+        assert_eq!(
+            explanation.headers,
+            [
+                HeaderRead {
+                    header_name: "x-envoy-internal".into(),
+                    selection: Selection::Single,
+                    lines: vec![],
+                    selected: vec![],
+                },
+                HeaderRead {
+                    header_name: "x-forwarded-for".into(),
+                    selection: Selection::All,
+                    lines: vec!["6.6.6.6, 1.1.1.1".into(), "2.2.2.2".into()],
+                    selected: vec![0, 1],
+                },
+            ]
+        );
+        assert_eq!(
+            explanation
+                .hops
+                .iter()
+                .map(|hop| (hop.entry.as_str(), hop.verdict))
+                .collect::<Vec<_>>(),
+            [
+                ("2.2.2.2", Verdict::Trusted),
+                ("1.1.1.1", Verdict::Selected),
+                ("6.6.6.6", Verdict::Ignored),
+            ]
+        );
+        assert_eq!(explanation.result, Ok("1.1.1.1".parse().unwrap()));
+
+        let explanation = ClientIpSource::RightmostXForwardedFor.explain(&headers);
+        assert_eq!(explanation.headers[0].selected, [1]);
+        assert_eq!(explanation.hops.len(), 1);
+
+        let explanation = ClientIpSource::XRealIp.explain(&headers);
+        assert!(explanation.headers[0].selected.is_empty());
+        assert!(explanation.hops.is_empty());
+        assert_eq!(
+            explanation.result,
+            Err(Error::SingleHeaderRequired {
+                header_name: X_REAL_IP
+            })
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "\
+source: x-real-ip
+header x-real-ip, must occur once:
+  [0] 1.1.1.1
+  [1] 2.2.2.2
+error: Multiple occurrences of the header aren't allowed: x-real-ip
+"
+        );
+
+        let explanation = ClientIpSource::Traefik { trusted_proxies: 5 }.explain(&headers);
+        assert!(
+            explanation
+                .hops
+                .iter()
+                .all(|hop| hop.verdict == Verdict::Trusted)
+        );
+        assert!(explanation.result.is_err());
+
+        let explanation = ClientIpSource::CfConnectingIp.explain(&headers);
+        assert_eq!(
+            explanation.to_string(),
+            "\
+source: cf-connecting-ip
+header cf-connecting-ip, must occur once: absent
+error: Missing required header: cf-connecting-ip
+"
+        );
+    }
+
+    #[test]
+    fn test_explain_malformed_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, foo, 2.2.2.2".parse().unwrap());
+
+        let explanation = ClientIpSource::Traefik { trusted_proxies: 1 }.explain(&headers);
+        assert_eq!(
+            explanation
+                .hops
+                .iter()
+                .map(|hop| (hop.entry.as_str(), hop.verdict))
+                .collect::<Vec<_>>(),
+            [
+                ("2.2.2.2", Verdict::Trusted),
+                ("foo", Verdict::Malformed),
+                ("1.1.1.1", Verdict::Ignored),
+            ]
+        );
+        assert!(explanation.result.is_err());
+    }
+
+    #[test]
+    fn test_explain_leftmost_non_private_x_forwarded_for() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "foo, 10.0.0.1, 1.1.1.1, 2.2.2.2".parse().unwrap(),
+        );
+
+        let explanation = leftmost_non_private_x_forwarded_for(&headers);
+        assert_eq!(
+            explanation
+                .hops
+                .iter()
+                .map(|hop| (hop.entry.as_str(), hop.verdict))
+                .collect::<Vec<_>>(),
+            [
+                ("foo", Verdict::SkippedMalformed),
+                ("10.0.0.1", Verdict::SkippedNonGlobal),
+                ("1.1.1.1", Verdict::Selected),
+                ("2.2.2.2", Verdict::Ignored),
+            ]
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "\
+extractor: leftmost_non_private_x_forwarded_for
+header x-forwarded-for, all lines used:
+  [0] foo, 10.0.0.1, 1.1.1.1, 2.2.2.2 (selected)
+hops of x-forwarded-for from the left:
+  foo: not an IP, skipped
+  10.0.0.1: not globally routable, skipped
+  1.1.1.1: selected
+  2.2.2.2: right of the client, ignored
+result: 1.1.1.1
+"
+        );
+    }
+
+    #[cfg(feature = "forwarded-header")]
+    #[test]
+    fn test_explain_forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "forwarded",
+            "for=1.1.1.1, for=_hidden;by=10.0.0.1".parse().unwrap(),
+        );
+
+        let explanation = rightmost_forwarded_resolved(&headers, &|_: &ForwardedNode| None);
+        assert_eq!(explanation.extractor, Extractor::RightmostForwardedResolved);
+        assert_eq!(
+            explanation
+                .hops
+                .iter()
+                .map(|hop| (hop.entry.as_str(), hop.verdict))
+                .collect::<Vec<_>>(),
+            [
+                ("for=_hidden;by=10.0.0.1", Verdict::Unresolved),
+                ("for=1.1.1.1", Verdict::Ignored),
+            ]
+        );
+
+        let resolver = |_: &ForwardedNode| Some("2.2.2.2".parse().unwrap());
+        let explanation = rightmost_forwarded_resolved(&headers, &resolver);
+        assert_eq!(explanation.hops[0].verdict, Verdict::Selected);
+        assert_eq!(explanation.result, Ok("2.2.2.2".parse().unwrap()));
+
+        let explanation =
+            rightmost_forwarded_by(&headers, &[ForwardedNode::Ip("10.0.0.2".parse().unwrap())]);
+        assert_eq!(explanation.hops[0].verdict, Verdict::UnknownBy);
+        assert!(matches!(
+            explanation.result,
+            Err(Error::ForwardedUnknownBy { .. })
+        ));
+    }
+
+    #[test]
+    fn test_explain_trusted_proxies() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "1.1.1.1".parse().unwrap());
+        let trusted_proxies: crate::TrustedProxies =
+            ["10.0.0.0/8".parse().unwrap()].into_iter().collect();
+
+        let explanation = trusted_proxies.explain(
+            &ClientIpSource::XRealIp,
+            &headers,
+            "5.6.7.8".parse().unwrap(),
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "\
+source: x-real-ip
+peer 5.6.7.8: not a trusted proxy, taken as the client
+result: 5.6.7.8
+"
+        );
+
+        let explanation = trusted_proxies.explain(
+            &ClientIpSource::XRealIp,
+            &headers,
+            "10.0.0.1".parse().unwrap(),
+        );
+        assert_eq!(
+            explanation.peer,
+            Some(PeerDecision {
+                ip: "10.0.0.1".parse().unwrap(),
+                trusted: true
+            })
+        );
+        assert_eq!(explanation.headers.len(), 1);
+        assert_eq!(explanation.result, Ok("1.1.1.1".parse().unwrap()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_explain_json() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "1.1.1.1".parse().unwrap());
+
+        assert_eq!(
+            serde_json::to_value(ClientIpSource::XRealIp.explain(&headers)).unwrap(),
+            serde_json::json!({
+                "extractor": {"source": "x-real-ip"},
+                "peer": null,
+                "headers": [{
+                    "header_name": "x-real-ip",
+                    "selection": "single",
+                    "lines": ["1.1.1.1"],
+                    "selected": [0],
+                }],
+                "hops_header_name": null,
+                "hops": [],
+                "result": {"ip": "1.1.1.1"},
+            })
+        );
+
+        headers.clear();
+        assert_eq!(
+            serde_json::to_value(ClientIpSource::XRealIp.explain(&headers)).unwrap()["result"],
+            serde_json::json!({"error": "Missing required header: x-real-ip"})
+        );
+    }
+}
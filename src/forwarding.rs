@@ -0,0 +1,796 @@
+//! Writing and converting forwarding headers
+//!
+//! Reverse proxies use [`ForwardingWriter`] to append their peer to
+//! `Forwarded` and `X-Forwarded-For` headers in a form the extractors of this
+//! crate parse back:
+//!
+//! ```
+//! use client_ip::forwarding::{ForwardedNode, ForwardingWriter};
+//! use http::HeaderMap;
+//!
+//! let writer = ForwardingWriter::new()
+//!     .by(ForwardedNode::Obfuscated("_gateway".into()))
+//!     .proto("https");
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("forwarded", "for=1.1.1.1".parse().unwrap());
+//! headers.insert("x-forwarded-for", "1.1.1.1".parse().unwrap());
+//! writer
+//!     .write(&mut headers, "[2001:db8::1]:1234".parse().unwrap())
+//!     .unwrap();
+//!
+//! assert_eq!(
+//!     headers["forwarded"],
+//!     r#"for=1.1.1.1, for="[2001:db8::1]";by=_gateway;proto=https"#
+//! );
+//! assert_eq!(headers["x-forwarded-for"], "1.1.1.1, 2001:db8::1");
+//! ```
+//!
+//! [`convert_to_forwarded`] and `convert_to_x_forwarded` (requires
+//! `forwarded-header` feature) translate between `Forwarded` and
+//! `X-Forwarded-For/Proto/Host` header families.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::BuildHasher,
+    net::{IpAddr, SocketAddr},
+};
+
+use http::{
+    HeaderMap, HeaderName, HeaderValue,
+    header::{FORWARDED, HOST, InvalidHeaderValue},
+};
+
+#[cfg(feature = "forwarded-header")]
+use crate::{AsciiHeaderValue, HeaderAccess};
+use crate::{
+    Error, Result,
+    header_names::{X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO},
+    list_header_entries,
+    raw::is_tchar,
+};
+
+/// Node identifier of a `Forwarded` element
+///
+/// <https://www.rfc-editor.org/rfc/rfc7239#section-6>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForwardedNode {
+    /// IP address without a port
+    Ip(IpAddr),
+    /// IP address with a port
+    SocketAddr(SocketAddr),
+    /// Obfuscated identifier, e.g. `_hidden`
+    Obfuscated(String),
+    /// The `unknown` identifier
+    Unknown,
+}
+
+/// A single element of `Forwarded` header
+///
+/// Formats as the header value, quoting values when required.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedElement {
+    /// The `for` parameter, the node making the request to the proxy
+    pub for_node: Option<ForwardedNode>,
+    /// The `by` parameter, the interface the proxy received the request on
+    pub by: Option<ForwardedNode>,
+    /// The `proto` parameter, the protocol used to make the request
+    pub proto: Option<String>,
+    /// The `host` parameter, the original `Host` request header
+    pub host: Option<String>,
+}
+
+/// Appends forwarding headers on behalf of a proxy
+///
+/// Writes both `Forwarded` and `X-Forwarded-For` headers by default. All the
+/// values of each header are combined into a single header line.
+#[derive(Debug, Clone)]
+pub struct ForwardingWriter {
+    by: Option<ForwardedNode>,
+    proto: Option<String>,
+    host: bool,
+    peer_port: bool,
+    forwarded: bool,
+    x_forwarded_for: bool,
+}
+
+impl ForwardedNode {
+    fn needs_quotes(&self) -> bool {
+        match self {
+            Self::Ip(ip) => ip.is_ipv6(),
+            Self::SocketAddr(_) => true,
+            Self::Obfuscated(s) => !is_token(s),
+            Self::Unknown => false,
+        }
+    }
+
+    #[cfg(feature = "forwarded-header")]
+    pub(crate) fn from_identifier(identifier: forwarded_header_value::Identifier) -> Self {
+        use forwarded_header_value::Identifier;
+
+        match identifier {
+            Identifier::SocketAddr(addr) => Self::SocketAddr(addr),
+            Identifier::IpAddr(ip) => Self::Ip(ip),
+            Identifier::String(s) => Self::Obfuscated(s),
+            Identifier::Unknown => Self::Unknown,
+        }
+    }
+}
+
+#[cfg(feature = "forwarded-header")]
+impl ForwardedElement {
+    pub(crate) fn from_stanza(stanza: forwarded_header_value::ForwardedStanza) -> Self {
+        use forwarded_header_value::Protocol;
+
+        Self {
+            for_node: stanza.forwarded_for.map(ForwardedNode::from_identifier),
+            by: stanza.forwarded_by.map(ForwardedNode::from_identifier),
+            proto: stanza.forwarded_proto.map(|proto| {
+                match proto {
+                    Protocol::Http => "http",
+                    Protocol::Https => "https",
+                }
+                .to_owned()
+            }),
+            host: stanza.forwarded_host,
+        }
+    }
+}
+
+impl fmt::Display for ForwardedNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(IpAddr::V4(ip)) => ip.fmt(f),
+            Self::Ip(IpAddr::V6(ip)) => write!(f, "[{ip}]"),
+            Self::SocketAddr(addr) => addr.fmt(f),
+            Self::Obfuscated(s) => s.fmt(f),
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+impl From<IpAddr> for ForwardedNode {
+    fn from(ip: IpAddr) -> Self {
+        Self::Ip(ip)
+    }
+}
+
+impl From<SocketAddr> for ForwardedNode {
+    fn from(addr: SocketAddr) -> Self {
+        Self::SocketAddr(addr)
+    }
+}
+
+impl fmt::Display for ForwardedElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        let mut pair = |f: &mut fmt::Formatter<'_>, name: &str, value: &str, quote: bool| {
+            f.write_str(separator)?;
+            separator = ";";
+            write!(f, "{name}=")?;
+            if quote || !is_token(value) {
+                write_quoted(f, value)
+            } else {
+                f.write_str(value)
+            }
+        };
+        if let Some(node) = &self.for_node {
+            pair(f, "for", &node.to_string(), node.needs_quotes())?;
+        }
+        if let Some(node) = &self.by {
+            pair(f, "by", &node.to_string(), node.needs_quotes())?;
+        }
+        if let Some(proto) = &self.proto {
+            pair(f, "proto", proto, false)?;
+        }
+        if let Some(host) = &self.host {
+            pair(f, "host", host, false)?;
+        }
+        Ok(())
+    }
+}
+
+impl ForwardingWriter {
+    /// Creates a writer of both `Forwarded` and `X-Forwarded-For` headers
+    pub fn new() -> Self {
+        Self {
+            by: None,
+            proto: None,
+            host: false,
+            peer_port: false,
+            forwarded: true,
+            x_forwarded_for: true,
+        }
+    }
+
+    /// Identifies the proxy with the `by` parameter of `Forwarded` elements
+    pub fn by(mut self, by: ForwardedNode) -> Self {
+        self.by = Some(by);
+        self
+    }
+
+    /// Sets the `proto` parameter of `Forwarded` elements, i.e. the protocol
+    /// the proxy receives requests with
+    pub fn proto(mut self, proto: impl Into<String>) -> Self {
+        self.proto = Some(proto.into());
+        self
+    }
+
+    /// Copies the `Host` request header into the `host` parameter of
+    /// `Forwarded` elements
+    pub fn host(mut self, host: bool) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Includes the peer port into the `for` parameter of `Forwarded`
+    /// elements, `X-Forwarded-For` entries never contain ports
+    pub fn peer_port(mut self, peer_port: bool) -> Self {
+        self.peer_port = peer_port;
+        self
+    }
+
+    /// Enables writing `Forwarded` header
+    pub fn forwarded(mut self, forwarded: bool) -> Self {
+        self.forwarded = forwarded;
+        self
+    }
+
+    /// Enables writing `X-Forwarded-For` header
+    pub fn x_forwarded_for(mut self, x_forwarded_for: bool) -> Self {
+        self.x_forwarded_for = x_forwarded_for;
+        self
+    }
+
+    /// Returns the `Forwarded` element describing the request
+    pub fn element(&self, header_map: &HeaderMap, peer_addr: SocketAddr) -> ForwardedElement {
+        ForwardedElement {
+            for_node: Some(if self.peer_port {
+                ForwardedNode::SocketAddr(peer_addr)
+            } else {
+                ForwardedNode::Ip(peer_addr.ip())
+            }),
+            by: self.by.clone(),
+            proto: self.proto.clone(),
+            host: self
+                .host
+                .then(|| header_map.get(HOST)?.to_str().ok().map(str::to_owned))
+                .flatten(),
+        }
+    }
+
+    /// Appends the peer to the forwarding headers of a request
+    ///
+    /// Fails if a configured identifier contains characters not allowed in
+    /// header values.
+    pub fn write(
+        &self,
+        header_map: &mut HeaderMap,
+        peer_addr: SocketAddr,
+    ) -> std::result::Result<(), InvalidHeaderValue> {
+        let forwarded = self
+            .forwarded
+            .then(|| append_entry(header_map, &FORWARDED, &self.element(header_map, peer_addr)))
+            .transpose()?;
+        let x_forwarded_for = self
+            .x_forwarded_for
+            .then(|| append_entry(header_map, &X_FORWARDED_FOR, &peer_addr.ip()))
+            .transpose()?;
+
+        if let Some(value) = forwarded {
+            header_map.insert(FORWARDED, value);
+        }
+        if let Some(value) = x_forwarded_for {
+            header_map.insert(X_FORWARDED_FOR, value);
+        }
+        Ok(())
+    }
+}
+
+impl Default for ForwardingWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Combines all values of the header and the entry into a single value
+fn append_entry(
+    header_map: &HeaderMap,
+    header_name: &HeaderName,
+    entry: &impl fmt::Display,
+) -> std::result::Result<HeaderValue, InvalidHeaderValue> {
+    let mut value = Vec::new();
+    for existing in header_map.get_all(header_name) {
+        let existing = existing.as_bytes().trim_ascii();
+        if !existing.is_empty() {
+            value.extend_from_slice(existing);
+            value.extend_from_slice(b", ");
+        }
+    }
+    value.extend_from_slice(entry.to_string().as_bytes());
+    HeaderValue::from_bytes(&value)
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(is_tchar)
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        if matches!(c, '"' | '\\') {
+            f.write_str("\\")?;
+        }
+        write!(f, "{c}")?;
+    }
+    f.write_str("\"")
+}
+
+/// Resolves obfuscated and `unknown` `Forwarded` nodes into IPs
+///
+/// Implemented for closures and for tables of obfuscated identifiers. Only
+/// use mappings of identifiers emitted by trusted proxies.
+pub trait ForwardedNodeResolver {
+    /// Returns the IP of a [`ForwardedNode::Obfuscated`] or
+    /// [`ForwardedNode::Unknown`] node, `None` if it's unknown to the resolver
+    fn resolve(&self, node: &ForwardedNode) -> Option<IpAddr>;
+}
+
+impl<F: Fn(&ForwardedNode) -> Option<IpAddr>> ForwardedNodeResolver for F {
+    fn resolve(&self, node: &ForwardedNode) -> Option<IpAddr> {
+        self(node)
+    }
+}
+
+impl<S: BuildHasher> ForwardedNodeResolver for HashMap<String, IpAddr, S> {
+    fn resolve(&self, node: &ForwardedNode) -> Option<IpAddr> {
+        match node {
+            ForwardedNode::Obfuscated(token) => self.get(token).copied(),
+            _ => None,
+        }
+    }
+}
+
+/// How conversion to `X-Forwarded-For` treats obfuscated and `unknown`
+/// nodes, which it can't represent as IPs
+///
+/// Elements without a `for` parameter are treated as `unknown` nodes, unless
+/// none of the elements has one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpaqueNodes {
+    /// Fails the conversion
+    #[default]
+    Reject,
+    /// Drops the entries
+    Skip,
+    /// Writes the identifiers as is, `X-Forwarded-For` extractors then treat
+    /// the entries as malformed
+    Keep,
+}
+
+/// Parses all `Forwarded` headers into elements in order of appearance
+///
+/// Returns an empty list if there's no `Forwarded` header.
+#[cfg(feature = "forwarded-header")]
+pub fn parse_forwarded(header_map: &impl HeaderAccess) -> Result<Vec<ForwardedElement>> {
+    use forwarded_header_value::ForwardedHeaderValue;
+
+    let header_values = match AsciiHeaderValue::of_all_headers(header_map, &FORWARDED) {
+        Ok(header_values) => header_values,
+        Err(Error::AbsentHeader { .. }) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut elements = Vec::new();
+    for header_value in header_values {
+        let stanzas = ForwardedHeaderValue::from_forwarded(header_value.0).map_err(|_| {
+            Error::MalformedHeaderValue {
+                header_name: FORWARDED,
+                header_value: header_value.0.to_owned(),
+            }
+        })?;
+        elements.extend(stanzas.into_iter().map(ForwardedElement::from_stanza));
+    }
+    Ok(elements)
+}
+
+/// Replaces `Forwarded` headers with `X-Forwarded-For`, `X-Forwarded-Proto`
+/// and `X-Forwarded-Host`
+///
+/// Ports and `by` parameters are dropped, protocol and host are taken from
+/// the first element as they describe the original request. Elements without
+/// `for` keep their hop positions as `unknown` entries, see [`OpaqueNodes`].
+/// Does nothing if there's no `Forwarded` header.
+#[cfg(feature = "forwarded-header")]
+pub fn convert_to_x_forwarded(header_map: &mut HeaderMap, opaque_nodes: OpaqueNodes) -> Result<()> {
+    let elements = parse_forwarded(header_map)?;
+    let Some(first) = elements.first() else {
+        return Ok(());
+    };
+
+    let has_for = elements.iter().any(|element| element.for_node.is_some());
+    let mut entries = Vec::new();
+    for node in elements
+        .iter()
+        .filter(|_| has_for)
+        .map(|element| element.for_node.as_ref().unwrap_or(&ForwardedNode::Unknown))
+    {
+        let entry = match node {
+            ForwardedNode::Ip(ip) => ip.to_string(),
+            ForwardedNode::SocketAddr(addr) => addr.ip().to_string(),
+            ForwardedNode::Obfuscated(_) | ForwardedNode::Unknown => match opaque_nodes {
+                OpaqueNodes::Reject => {
+                    let header_value = header_map
+                        .get_all(FORWARDED)
+                        .iter()
+                        .filter_map(|v| v.to_str().ok())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    return Err(match node {
+                        ForwardedNode::Unknown => Error::ForwardedUnknown { header_value },
+                        _ => Error::ForwardedObfuscated { header_value },
+                    });
+                }
+                OpaqueNodes::Skip => continue,
+                OpaqueNodes::Keep => node.to_string(),
+            },
+        };
+        entries.push(entry);
+    }
+
+    let values = [
+        (
+            X_FORWARDED_FOR,
+            (!entries.is_empty()).then(|| entries.join(", ")),
+        ),
+        (X_FORWARDED_PROTO, first.proto.clone()),
+        (X_FORWARDED_HOST, first.host.clone()),
+    ];
+    header_map.remove(FORWARDED);
+    for (header_name, value) in values {
+        header_map.remove(&header_name);
+        if let Some(value) = value {
+            let value =
+                HeaderValue::try_from(value).expect("parsed from a visible ASCII header value");
+            header_map.insert(header_name, value);
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+/// headers with `Forwarded`
+///
+/// Protocol and host go to the first element as they describe the original
+/// request, only the leftmost entry of `X-Forwarded-Proto` and
+/// `X-Forwarded-Host` appended to by several proxies is used. `unknown` and
+/// obfuscated `X-Forwarded-For` entries are kept as such. Does nothing if
+/// there are no such headers.
+pub fn convert_to_forwarded(header_map: &mut HeaderMap) -> Result<()> {
+    let mut elements = Vec::new();
+    match list_header_entries(header_map, &X_FORWARDED_FOR) {
+        Ok(entries) => {
+            for (header_value, entry) in entries.into_iter().filter(|(_, e)| !e.is_empty()) {
+                let node = if entry == "unknown" {
+                    ForwardedNode::Unknown
+                } else if entry.starts_with('_') && is_token(entry) {
+                    ForwardedNode::Obfuscated(entry.to_owned())
+                } else if let Ok(ip) = entry.parse() {
+                    ForwardedNode::Ip(ip)
+                } else if let Ok(addr) = entry.parse() {
+                    ForwardedNode::SocketAddr(addr)
+                } else {
+                    return Err(Error::MalformedHeaderValue {
+                        header_name: X_FORWARDED_FOR,
+                        header_value: header_value.to_owned(),
+                    });
+                };
+                elements.push(ForwardedElement {
+                    for_node: Some(node),
+                    ..Default::default()
+                });
+            }
+        }
+        Err(Error::AbsentHeader { .. }) => (),
+        Err(e) => return Err(e),
+    }
+
+    let leftmost_entry =
+        |header_name: &HeaderName| match list_header_entries(header_map, header_name) {
+            Ok(entries) => Ok(entries
+                .into_iter()
+                .map(|(_, entry)| entry)
+                .find(|entry| !entry.is_empty())
+                .map(str::to_owned)),
+            Err(Error::AbsentHeader { .. }) => Ok(None),
+            Err(e) => Err(e),
+        };
+    let proto = leftmost_entry(&X_FORWARDED_PROTO)?;
+    let host = leftmost_entry(&X_FORWARDED_HOST)?;
+    if proto.is_some() || host.is_some() {
+        if elements.is_empty() {
+            elements.push(ForwardedElement::default());
+        }
+        elements[0].proto = proto;
+        elements[0].host = host;
+    }
+    if elements.is_empty() {
+        return Ok(());
+    }
+
+    let value = elements
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    header_map.remove(X_FORWARDED_FOR);
+    header_map.remove(X_FORWARDED_PROTO);
+    header_map.remove(X_FORWARDED_HOST);
+    header_map.insert(
+        FORWARDED,
+        HeaderValue::try_from(value).expect("formatted from visible ASCII header values"),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rightmost_x_forwarded_for;
+
+    #[test]
+    fn test_forwarded_element() {
+        for (element, expected) in [
+            (ForwardedElement::default(), ""),
+            (
+                ForwardedElement {
+                    for_node: Some(ForwardedNode::Ip("192.0.2.43".parse().unwrap())),
+                    ..Default::default()
+                },
+                "for=192.0.2.43",
+            ),
+            (
+                ForwardedElement {
+                    for_node: Some(ForwardedNode::Ip("2001:db8:cafe::17".parse().unwrap())),
+                    by: Some(ForwardedNode::Unknown),
+                    ..Default::default()
+                },
+                r#"for="[2001:db8:cafe::17]";by=unknown"#,
+            ),
+            (
+                ForwardedElement {
+                    for_node: Some(ForwardedNode::SocketAddr(
+                        "192.0.2.43:47011".parse().unwrap(),
+                    )),
+                    by: Some(ForwardedNode::SocketAddr(
+                        "[2001:db8:cafe::17]:4711".parse().unwrap(),
+                    )),
+                    proto: Some("https".into()),
+                    host: Some("example.com:8080".into()),
+                },
+                r#"for="192.0.2.43:47011";by="[2001:db8:cafe::17]:4711";proto=https;host="example.com:8080""#,
+            ),
+            (
+                ForwardedElement {
+                    for_node: Some(ForwardedNode::Obfuscated("_hidden".into())),
+                    host: Some(r#"a"b\c"#.into()),
+                    ..Default::default()
+                },
+                r#"for=_hidden;host="a\"b\\c""#,
+            ),
+        ] {
+            assert_eq!(element.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, "example.com".parse().unwrap());
+        headers.append(X_FORWARDED_FOR, "1.1.1.1".parse().unwrap());
+        headers.append(X_FORWARDED_FOR, "2.2.2.2 ".parse().unwrap());
+
+        ForwardingWriter::new()
+            .host(true)
+            .write(&mut headers, "3.3.3.3:1234".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            headers.get_all(X_FORWARDED_FOR).iter().collect::<Vec<_>>(),
+            ["1.1.1.1, 2.2.2.2, 3.3.3.3"]
+        );
+        assert_eq!(headers[FORWARDED], "for=3.3.3.3;host=example.com");
+        assert_eq!(
+            rightmost_x_forwarded_for(&headers),
+            Ok("3.3.3.3".parse().unwrap())
+        );
+
+        ForwardingWriter::new()
+            .x_forwarded_for(false)
+            .peer_port(true)
+            .write(&mut headers, "[::1]:80".parse().unwrap())
+            .unwrap();
+        assert_eq!(headers[X_FORWARDED_FOR], "1.1.1.1, 2.2.2.2, 3.3.3.3");
+        assert_eq!(
+            headers[FORWARDED],
+            r#"for=3.3.3.3;host=example.com, for="[::1]:80""#
+        );
+
+        assert!(
+            ForwardingWriter::new()
+                .proto("\n")
+                .write(&mut headers, "[::1]:80".parse().unwrap())
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "forwarded-header")]
+    #[test]
+    fn test_round_trip() {
+        use forwarded_header_value::{ForwardedHeaderValue, Identifier, Protocol};
+
+        use crate::rightmost_forwarded;
+
+        for (peer_addr, peer_port) in [
+            ("1.2.3.4:80", false),
+            ("1.2.3.4:80", true),
+            ("[2001:db8::1]:443", false),
+            ("[2001:db8::1]:443", true),
+        ] {
+            let peer_addr: SocketAddr = peer_addr.parse().unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert(HOST, r#"exa"mple.com"#.parse().unwrap());
+            headers.insert(FORWARDED, "for=5.6.7.8".parse().unwrap());
+            ForwardingWriter::new()
+                .by(ForwardedNode::SocketAddr(
+                    "[2001:db8::2]:8080".parse().unwrap(),
+                ))
+                .proto("https")
+                .host(true)
+                .peer_port(peer_port)
+                .write(&mut headers, peer_addr)
+                .unwrap();
+
+            assert_eq!(rightmost_forwarded(&headers), Ok(peer_addr.ip()));
+            assert_eq!(rightmost_x_forwarded_for(&headers), Ok(peer_addr.ip()));
+
+            let value =
+                ForwardedHeaderValue::from_forwarded(headers[FORWARDED].to_str().unwrap()).unwrap();
+            assert_eq!(value.len(), 2);
+            let element = value.proximate();
+            assert_eq!(
+                element.forwarded_for,
+                Some(if peer_port {
+                    Identifier::SocketAddr(peer_addr)
+                } else {
+                    Identifier::IpAddr(peer_addr.ip())
+                })
+            );
+            assert_eq!(
+                element.forwarded_by,
+                Some(Identifier::SocketAddr(
+                    "[2001:db8::2]:8080".parse().unwrap()
+                ))
+            );
+            assert_eq!(element.forwarded_proto, Some(Protocol::Https));
+            assert_eq!(element.forwarded_host.as_deref(), Some(r#"exa"mple.com"#));
+        }
+    }
+
+    #[test]
+    fn test_convert_to_forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.append(X_FORWARDED_FOR, "1.1.1.1, unknown".parse().unwrap());
+        headers.append(X_FORWARDED_FOR, "_hidden, [::1]:80".parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "https".parse().unwrap());
+        headers.insert(X_FORWARDED_HOST, "example.com".parse().unwrap());
+        convert_to_forwarded(&mut headers).unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(
+            headers[FORWARDED],
+            r#"for=1.1.1.1;proto=https;host=example.com, for=unknown, for=_hidden, for="[::1]:80""#
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_PROTO, "http".parse().unwrap());
+        convert_to_forwarded(&mut headers).unwrap();
+        assert_eq!(headers[FORWARDED], "proto=http");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, "1.1.1.1, 10.0.0.1".parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "https, http".parse().unwrap());
+        headers.append(X_FORWARDED_HOST, "example.com".parse().unwrap());
+        headers.append(X_FORWARDED_HOST, "internal".parse().unwrap());
+        convert_to_forwarded(&mut headers).unwrap();
+        assert_eq!(
+            headers[FORWARDED], "for=1.1.1.1;proto=https;host=example.com, for=10.0.0.1",
+            "multi-hop protocol and host"
+        );
+
+        let mut headers = HeaderMap::new();
+        convert_to_forwarded(&mut headers).unwrap();
+        assert!(headers.is_empty());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, "1.1.1.1, foo".parse().unwrap());
+        assert_eq!(
+            convert_to_forwarded(&mut headers),
+            Err(Error::MalformedHeaderValue {
+                header_name: X_FORWARDED_FOR,
+                header_value: "1.1.1.1, foo".into()
+            })
+        );
+        assert_eq!(headers.len(), 1, "headers are kept on errors");
+    }
+
+    #[cfg(feature = "forwarded-header")]
+    #[test]
+    fn test_convert_to_x_forwarded() {
+        let forwarded = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(FORWARDED, value.parse().unwrap());
+            headers
+        };
+
+        let mut headers = forwarded(
+            r#"for="1.1.1.1:80";by=_proxy;proto=https;host=example.com, for="[::1]";proto=http"#,
+        );
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Reject).unwrap();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[X_FORWARDED_FOR], "1.1.1.1, ::1");
+        assert_eq!(headers[X_FORWARDED_PROTO], "https");
+        assert_eq!(headers[X_FORWARDED_HOST], "example.com");
+
+        let value = "for=1.1.1.1, for=_hidden, for=unknown";
+        assert_eq!(
+            convert_to_x_forwarded(&mut forwarded(value), OpaqueNodes::Reject),
+            Err(Error::ForwardedObfuscated {
+                header_value: value.into()
+            })
+        );
+        assert_eq!(
+            convert_to_x_forwarded(&mut forwarded("for=unknown"), OpaqueNodes::Reject),
+            Err(Error::ForwardedUnknown {
+                header_value: "for=unknown".into()
+            })
+        );
+
+        let mut headers = forwarded(value);
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Skip).unwrap();
+        assert_eq!(headers[X_FORWARDED_FOR], "1.1.1.1");
+
+        let mut headers = forwarded(value);
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Keep).unwrap();
+        assert_eq!(headers[X_FORWARDED_FOR], "1.1.1.1, _hidden, unknown");
+        convert_to_forwarded(&mut headers).unwrap();
+        assert_eq!(headers[FORWARDED], value, "round trip");
+
+        let mut headers = forwarded("for=1.1.1.1;proto=https;host=a.com, for=\"[::1]\"");
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Reject).unwrap();
+        convert_to_forwarded(&mut headers).unwrap();
+        assert_eq!(
+            headers[FORWARDED], "for=1.1.1.1;proto=https;host=a.com, for=\"[::1]\"",
+            "round trip"
+        );
+
+        let value = "for=1.1.1.1, proto=https, for=2.2.2.2";
+        assert_eq!(
+            convert_to_x_forwarded(&mut forwarded(value), OpaqueNodes::Reject),
+            Err(Error::ForwardedUnknown {
+                header_value: value.into()
+            }),
+            "element without for"
+        );
+        let mut headers = forwarded(value);
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Keep).unwrap();
+        assert_eq!(headers[X_FORWARDED_FOR], "1.1.1.1, unknown, 2.2.2.2");
+        let mut headers = forwarded(value);
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Skip).unwrap();
+        assert_eq!(headers[X_FORWARDED_FOR], "1.1.1.1, 2.2.2.2");
+
+        let mut headers = forwarded("proto=https");
+        convert_to_x_forwarded(&mut headers, OpaqueNodes::Reject).unwrap();
+        assert_eq!(headers.get(X_FORWARDED_FOR), None);
+        assert_eq!(headers[X_FORWARDED_PROTO], "https");
+    }
+}
@@ -0,0 +1,17 @@
+use http::{HeaderMap, HeaderName};
+
+/// Read access to request headers, the extractors run on
+///
+/// Implemented for [`http::HeaderMap`] and for header representations of
+/// frameworks not based on [`http`] (enabled by the corresponding features).
+pub trait HeaderAccess {
+    /// Returns raw values of all occurrences of the header in order of
+    /// appearance
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]>;
+}
+
+impl HeaderAccess for HeaderMap {
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+        self.get_all(header_name).into_iter().map(|v| v.as_bytes())
+    }
+}
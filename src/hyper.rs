@@ -0,0 +1,261 @@
+//! [`hyper`](https://docs.rs/hyper) 1.x connection-level integration
+//!
+//! [`ClientIpAcceptor`] captures the peer address of an accepted connection
+//! (optionally consuming a [PROXY protocol] preamble) and wraps the
+//! connection's service into [`ClientIpService`], inserting the resolved
+//! [`ClientIp`] into request extensions:
+//!
+//! ```no_run
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource, hyper::ClientIpAcceptor};
+//! use hyper::{Request, Response, body::Incoming, service::service_fn};
+//!
+//! async fn handler(req: Request<Incoming>) -> Result<Response<String>, std::convert::Infallible> {
+//!     let ip = req.extensions().get::<ClientIp>().map(|ClientIp(ip)| ip.to_string());
+//!     Ok(Response::new(ip.unwrap_or_default()))
+//! }
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let acceptor = ClientIpAcceptor::new().config(
+//!     ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+//!         .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+//! );
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+//! loop {
+//!     let (mut stream, peer_addr) = listener.accept().await?;
+//!     let connection = acceptor.accept(&mut stream, peer_addr).await?;
+//!     let service = acceptor.service(connection, service_fn(handler));
+//!     // Serve `stream` with `service` using `hyper::server::conn`
+//! }
+//! # }
+//! ```
+//!
+//! [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::{io, net::SocketAddr};
+
+use http::Request;
+use hyper::service::Service;
+use tokio::io::AsyncRead;
+
+use crate::{ClientIp, ClientIpConfig, Error};
+
+mod proxy_protocol;
+
+/// Addresses of an accepted connection, inserted into request extensions by
+/// [`ClientIpService`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Peer address of the TCP connection
+    pub peer_addr: SocketAddr,
+    /// Source address from the PROXY protocol preamble
+    pub proxied_addr: Option<SocketAddr>,
+}
+
+impl ConnectionInfo {
+    /// Address of the connection client: the PROXY protocol source address if
+    /// present, the peer address otherwise
+    pub fn client_addr(&self) -> SocketAddr {
+        self.proxied_addr.unwrap_or(self.peer_addr)
+    }
+}
+
+/// Resolves the client IP of connections accepted by a hyper server
+///
+/// Without a [`ClientIpConfig`] the connection client address is used as the
+/// client IP. With a configuration, the connection client address is its peer
+/// IP.
+#[derive(Debug, Clone, Default)]
+pub struct ClientIpAcceptor {
+    config: Option<ClientIpConfig>,
+    proxy_protocol: bool,
+}
+
+impl ClientIpAcceptor {
+    /// Creates an acceptor using the connection client address as the client
+    /// IP
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration extracting the client IP from request headers
+    pub fn config(mut self, config: ClientIpConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Requires a PROXY protocol (v1 or v2) preamble on every connection
+    pub fn proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Captures addresses of an accepted connection, consuming the PROXY
+    /// protocol preamble if enabled
+    ///
+    /// The preamble is read without buffering, so the stream can be served
+    /// afterwards as is.
+    pub async fn accept<IO>(&self, io: &mut IO, peer_addr: SocketAddr) -> io::Result<ConnectionInfo>
+    where
+        IO: AsyncRead + Unpin,
+    {
+        let proxied_addr = if self.proxy_protocol {
+            proxy_protocol::read_header(io).await?
+        } else {
+            None
+        };
+        Ok(ConnectionInfo {
+            peer_addr,
+            proxied_addr,
+        })
+    }
+
+    /// Wraps the service of an accepted connection
+    pub fn service<S>(&self, connection: ConnectionInfo, inner: S) -> ClientIpService<S> {
+        ClientIpService {
+            inner,
+            connection,
+            acceptor: self.clone(),
+        }
+    }
+
+    fn resolve<B>(&self, connection: &ConnectionInfo, req: &Request<B>) -> Result<ClientIp, Error> {
+        let client_ip = connection.client_addr().ip();
+        let ip = match &self.config {
+            None => client_ip.to_canonical(),
+            Some(config) => config.extract_with_peer(req.headers(), client_ip)?,
+        };
+        Ok(ClientIp(ip))
+    }
+}
+
+/// A [`Service`] inserting [`ConnectionInfo`] and either [`ClientIp`] or
+/// [`Error`] into request extensions
+#[derive(Debug, Clone)]
+pub struct ClientIpService<S> {
+    inner: S,
+    connection: ConnectionInfo,
+    acceptor: ClientIpAcceptor,
+}
+
+impl<S, B> Service<Request<B>> for ClientIpService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        match self.acceptor.resolve(&self.connection, &req) {
+            Ok(client_ip) => req.extensions_mut().insert(client_ip).map(drop),
+            Err(e) => req.extensions_mut().insert(e).map(drop),
+        };
+        req.extensions_mut().insert(self.connection);
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::{Ready, ready},
+    };
+
+    use http::HeaderName;
+
+    use super::*;
+    use crate::ClientIpSource;
+
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = String;
+        type Error = Infallible;
+        type Future = Ready<Result<String, Infallible>>;
+
+        fn call(&self, req: Request<()>) -> Self::Future {
+            let extensions = req.extensions();
+            ready(Ok(format!(
+                "{:?} {:?}",
+                extensions.get::<ClientIp>().map(|ClientIp(ip)| ip),
+                extensions.get::<Error>(),
+            )))
+        }
+    }
+
+    async fn call(acceptor: &ClientIpAcceptor, preamble: &[u8], x_real_ip: Option<&str>) -> String {
+        let mut io = preamble;
+        let connection = acceptor
+            .accept(&mut io, "10.0.0.1:1234".parse().unwrap())
+            .await
+            .unwrap();
+        assert!(io.is_empty(), "preamble isn't fully consumed");
+
+        let mut builder = Request::builder();
+        if let Some(ip) = x_real_ip {
+            builder = builder.header("x-real-ip", ip);
+        }
+        acceptor
+            .service(connection, Echo)
+            .call(builder.body(()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_service() {
+        let acceptor = ClientIpAcceptor::new();
+        assert_eq!(
+            call(&acceptor, b"", Some("1.2.3.4")).await,
+            "Some(10.0.0.1) None"
+        );
+
+        let acceptor = ClientIpAcceptor::new().proxy_protocol(true);
+        assert_eq!(
+            call(
+                &acceptor,
+                b"PROXY TCP4 5.6.7.8 10.0.0.2 56324 443\r\n",
+                Some("1.2.3.4")
+            )
+            .await,
+            "Some(5.6.7.8) None"
+        );
+
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        let acceptor = ClientIpAcceptor::new().config(config.clone());
+        assert_eq!(
+            call(&acceptor, b"", Some("1.2.3.4")).await,
+            "Some(1.2.3.4) None"
+        );
+        assert_eq!(
+            call(&acceptor, b"", None).await,
+            format!(
+                "None {:?}",
+                Some(Error::AbsentHeader {
+                    header_name: HeaderName::from_static("x-real-ip")
+                })
+            )
+        );
+
+        let acceptor = ClientIpAcceptor::new()
+            .config(config.trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()));
+        assert_eq!(
+            call(&acceptor, b"", Some("1.2.3.4")).await,
+            "Some(1.2.3.4) None",
+            "trusted peer"
+        );
+
+        let acceptor = acceptor.proxy_protocol(true);
+        assert_eq!(
+            call(
+                &acceptor,
+                b"PROXY TCP4 5.6.7.8 10.0.0.2 56324 443\r\n",
+                Some("1.2.3.4")
+            )
+            .await,
+            "Some(5.6.7.8) None",
+            "untrusted PROXY protocol source"
+        );
+    }
+}
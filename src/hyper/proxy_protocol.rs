@@ -0,0 +1,215 @@
+//! [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt)
+//! header parsing
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The shortest possible header: `PROXY UNKNOWN\r\n`
+const V1_MIN_LEN: usize = 15;
+const V1_MAX_LEN: usize = 107;
+
+/// Reads a v1 or v2 header, returns the source address unless the header
+/// carries none (`UNKNOWN` / `LOCAL` connections)
+///
+/// Reads byte-by-byte past the shortest header length to never consume
+/// application data.
+pub(super) async fn read_header<IO>(io: &mut IO) -> io::Result<Option<SocketAddr>>
+where
+    IO: AsyncRead + Unpin,
+{
+    let mut header = vec![0; V1_MIN_LEN];
+    io.read_exact(&mut header).await?;
+
+    if header.starts_with(V2_SIGNATURE) {
+        header.push(io.read_u8().await?);
+        let len = u16::from_be_bytes([header[14], header[15]]);
+        let mut addresses = vec![0; len.into()];
+        io.read_exact(&mut addresses).await?;
+        parse_v2(header[12], header[13], &addresses)
+    } else if header.starts_with(b"PROXY ") {
+        // A line ending within the shortest header can't end a valid one
+        if header[..V1_MIN_LEN - 2].windows(2).any(|w| w == b"\r\n") {
+            return Err(invalid("v1 header is too short"));
+        }
+        while !header.ends_with(b"\r\n") {
+            if header.len() >= V1_MAX_LEN {
+                return Err(invalid("v1 header is too long"));
+            }
+            header.push(io.read_u8().await?);
+        }
+        parse_v1(&header[..header.len() - 2])
+    } else {
+        Err(invalid("missing header"))
+    }
+}
+
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("non-ASCII v1 header"))?;
+    let mut parts = line.split(' ').skip(1);
+    match parts.next() {
+        Some("UNKNOWN") => Ok(None),
+        Some("TCP4" | "TCP6") => {
+            let ip = parts
+                .next()
+                .and_then(|s| s.parse::<IpAddr>().ok())
+                .ok_or_else(|| invalid("malformed v1 source address"))?;
+            let port = parts
+                .nth(1)
+                .and_then(|s| s.parse::<u16>().ok())
+                .ok_or_else(|| invalid("malformed v1 source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("unsupported v1 protocol")),
+    }
+}
+
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL: health checks etc. of the proxy itself
+        0 => return Ok(None),
+        // PROXY
+        1 => (),
+        _ => return Err(invalid("unsupported v2 command")),
+    }
+
+    let (ip, port) = match family >> 4 {
+        // AF_INET
+        1 => {
+            let a = addresses
+                .get(..12)
+                .ok_or_else(|| invalid("truncated v2 IPv4 addresses"))?;
+            let ip: [u8; 4] = a[..4].try_into().expect("slice of 4");
+            (IpAddr::from(Ipv4Addr::from(ip)), [a[8], a[9]])
+        }
+        // AF_INET6
+        2 => {
+            let a = addresses
+                .get(..36)
+                .ok_or_else(|| invalid("truncated v2 IPv6 addresses"))?;
+            let ip: [u8; 16] = a[..16].try_into().expect("slice of 16");
+            (IpAddr::from(Ipv6Addr::from(ip)), [a[32], a[33]])
+        }
+        // AF_UNSPEC, AF_UNIX
+        0 | 3 => return Ok(None),
+        _ => return Err(invalid("unsupported v2 address family")),
+    };
+    Ok(Some(SocketAddr::new(ip, u16::from_be_bytes(port))))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("PROXY protocol: {message}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(mut bytes: &[u8]) -> io::Result<(Option<SocketAddr>, &[u8])> {
+        let addr = read_header(&mut bytes).await?;
+        Ok((addr, bytes))
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([0x20 | command, family]);
+        header.extend(u16::try_from(addresses.len()).unwrap().to_be_bytes());
+        header.extend(addresses);
+        header.extend(b"GET");
+        header
+    }
+
+    #[tokio::test]
+    async fn test_v1() {
+        assert_eq!(
+            read(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET")
+                .await
+                .unwrap(),
+            (Some("192.168.0.1:56324".parse().unwrap()), &b"GET"[..])
+        );
+        assert_eq!(
+            read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\nGET")
+                .await
+                .unwrap(),
+            (Some("[2001:db8::1]:56324".parse().unwrap()), &b"GET"[..])
+        );
+        assert_eq!(
+            read(b"PROXY UNKNOWN\r\nGET").await.unwrap(),
+            (None, &b"GET"[..])
+        );
+
+        for header in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"PROXY TCP4 foo 192.168.0.11 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 foo 443\r\n",
+            b"PROXY UDP4 192.168.0.1 192.168.0.11 56324 443\r\n",
+            &[b'x'; 200],
+        ] {
+            assert_eq!(
+                read(header).await.unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+        assert_eq!(
+            read(b"PROXY TCP4 192.168").await.unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        assert_eq!(
+            read(b"PROXY UNKNOWN\r\n").await.unwrap(),
+            (None, &b""[..]),
+            "shortest header"
+        );
+        let mut bytes = &b"PROXY TCP4\r\nGET / HTTP/1.1\r\n\r\n"[..];
+        assert_eq!(
+            read_header(&mut bytes).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            bytes, b" / HTTP/1.1\r\n\r\n",
+            "no reading past a short header"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_v2() {
+        let ipv4 = [192, 168, 0, 1, 192, 168, 0, 11, 0xdc, 0x04, 0x01, 0xbb];
+        assert_eq!(
+            read(&v2(1, 0x11, &ipv4)).await.unwrap(),
+            (Some("192.168.0.1:56324".parse().unwrap()), &b"GET"[..])
+        );
+
+        let mut ipv6 = Vec::new();
+        ipv6.extend("2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6.extend("2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6.extend([0xdc, 0x04, 0x01, 0xbb]);
+        assert_eq!(
+            read(&v2(1, 0x21, &ipv6)).await.unwrap(),
+            (Some("[2001:db8::1]:56324".parse().unwrap()), &b"GET"[..])
+        );
+
+        assert_eq!(
+            read(&v2(0, 0x00, &[])).await.unwrap(),
+            (None, &b"GET"[..]),
+            "LOCAL command"
+        );
+        assert_eq!(
+            read(&v2(1, 0x11, &ipv4[..8])).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            read(&v2(2, 0x11, &ipv4)).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Classification of an IP address by the special-purpose range it belongs to
+///
+/// Based on the [IPv4] and [IPv6] special-purpose address registries.
+/// IPv4-mapped IPv6 addresses (`::ffff:0:0/96`) are classified by the embedded
+/// IPv4 address.
+///
+/// [IPv4]: https://www.iana.org/assignments/iana-ipv4-special-registry/iana-ipv4-special-registry.xhtml
+/// [IPv6]: https://www.iana.org/assignments/iana-ipv6-special-registry/iana-ipv6-special-registry.xhtml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpClass {
+    /// Globally routable address
+    Global,
+    /// `0.0.0.0`, `::`
+    Unspecified,
+    /// `127.0.0.0/8`, `::1`
+    Loopback,
+    /// RFC 1918: `10.0.0.0/8`, `172.16.0.0/12`, `192.168.0.0/16`
+    Private,
+    /// RFC 6598 shared address space used by carrier-grade NAT: `100.64.0.0/10`
+    SharedCgnat,
+    /// `169.254.0.0/16`, `fe80::/10`
+    LinkLocal,
+    /// RFC 4193 unique local addresses: `fc00::/7`
+    UniqueLocal,
+    /// RFC 5737: `192.0.2.0/24`, `198.51.100.0/24`, `203.0.113.0/24`,
+    /// RFC 3849: `2001:db8::/32`, RFC 9637: `3fff::/20`
+    Documentation,
+    /// RFC 2544: `198.18.0.0/15`, RFC 5180: `2001:2::/48`
+    Benchmarking,
+    /// `224.0.0.0/4`, `ff00::/8`
+    Multicast,
+    /// `255.255.255.255`
+    Broadcast,
+    /// Any other range not meant for public hosts, e.g. `0.0.0.0/8`,
+    /// `240.0.0.0/4`, `192.0.0.0/24`, `2001::/23`, `100::/64` or addresses
+    /// outside of `2000::/3`
+    Reserved,
+}
+
+impl IpClass {
+    /// Checks if the class is [`IpClass::Global`]
+    pub fn is_global(self) -> bool {
+        self == Self::Global
+    }
+}
+
+/// Classifies an IP address, see [`IpClass`]
+pub fn classify(ip: IpAddr) -> IpClass {
+    match ip {
+        IpAddr::V4(ip) => classify_v4(ip),
+        IpAddr::V6(ip) => classify_v6(ip),
+    }
+}
+
+fn classify_v4(ip: Ipv4Addr) -> IpClass {
+    let [a, b, c, d] = ip.octets();
+
+    if ip.is_unspecified() {
+        IpClass::Unspecified
+    } else if ip.is_broadcast() {
+        IpClass::Broadcast
+    } else if ip.is_loopback() {
+        IpClass::Loopback
+    } else if ip.is_private() {
+        IpClass::Private
+    } else if a == 100 && b & 0b1100_0000 == 0b0100_0000 {
+        IpClass::SharedCgnat
+    } else if ip.is_link_local() {
+        IpClass::LinkLocal
+    } else if ip.is_documentation() {
+        IpClass::Documentation
+    } else if a == 198 && b & 0b1111_1110 == 18 {
+        IpClass::Benchmarking
+    } else if ip.is_multicast() {
+        IpClass::Multicast
+    } else if a == 0 || a >= 240 {
+        IpClass::Reserved
+    } else if a == 192 && b == 0 && c == 0 {
+        // IETF protocol assignments, except globally reachable anycast
+        // addresses of PCP and TURN
+        if d == 9 || d == 10 {
+            IpClass::Global
+        } else {
+            IpClass::Reserved
+        }
+    } else {
+        IpClass::Global
+    }
+}
+
+fn classify_v6(ip: Ipv6Addr) -> IpClass {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return classify_v4(ip);
+    }
+
+    let segments = ip.segments();
+    if ip.is_unspecified() {
+        IpClass::Unspecified
+    } else if ip.is_loopback() {
+        IpClass::Loopback
+    } else if ip.is_multicast() {
+        IpClass::Multicast
+    } else if ip.is_unique_local() {
+        IpClass::UniqueLocal
+    } else if ip.is_unicast_link_local() {
+        IpClass::LinkLocal
+    } else if matches!(segments, [0x2001, 0xdb8, ..])
+        || matches!(segments, [0x3fff, 0..=0x0fff, ..])
+    {
+        IpClass::Documentation
+    } else if matches!(segments, [0x2001, 0x2, 0, ..]) {
+        IpClass::Benchmarking
+    } else if matches!(segments, [0x2001, 0..=0x01ff, ..]) {
+        // IETF protocol assignments, except globally reachable ones
+        if u128::from(ip) == 0x2001_0001_0000_0000_0000_0000_0000_0001
+            || u128::from(ip) == 0x2001_0001_0000_0000_0000_0000_0000_0002
+            || matches!(segments, [0x2001, 0x3, ..])
+            || matches!(segments, [0x2001, 0x4, 0x112, ..])
+            || matches!(segments, [0x2001, 0x20..=0x2f, ..])
+        {
+            IpClass::Global
+        } else {
+            IpClass::Reserved
+        }
+    } else if matches!(segments, [0x64, 0xff9b, 0, 0, 0, 0, ..]) {
+        // NAT64 well-known prefix
+        IpClass::Global
+    } else if segments[0] & 0xe000 != 0x2000 {
+        IpClass::Reserved
+    } else {
+        IpClass::Global
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        for (ip, class) in [
+            ("1.1.1.1", IpClass::Global),
+            ("8.8.8.8", IpClass::Global),
+            ("0.0.0.0", IpClass::Unspecified),
+            ("0.1.2.3", IpClass::Reserved),
+            ("10.1.2.3", IpClass::Private),
+            ("100.63.255.255", IpClass::Global),
+            ("100.64.0.0", IpClass::SharedCgnat),
+            ("100.127.255.255", IpClass::SharedCgnat),
+            ("100.128.0.0", IpClass::Global),
+            ("127.0.0.1", IpClass::Loopback),
+            ("169.254.1.2", IpClass::LinkLocal),
+            ("172.16.0.1", IpClass::Private),
+            ("172.32.0.1", IpClass::Global),
+            ("192.0.0.1", IpClass::Reserved),
+            ("192.0.0.9", IpClass::Global),
+            ("192.0.2.1", IpClass::Documentation),
+            ("192.168.1.1", IpClass::Private),
+            ("198.18.0.1", IpClass::Benchmarking),
+            ("198.19.255.255", IpClass::Benchmarking),
+            ("198.20.0.1", IpClass::Global),
+            ("198.51.100.1", IpClass::Documentation),
+            ("203.0.113.1", IpClass::Documentation),
+            ("224.0.0.1", IpClass::Multicast),
+            ("240.0.0.1", IpClass::Reserved),
+            ("255.255.255.255", IpClass::Broadcast),
+            ("2606:4700::1111", IpClass::Global),
+            ("::", IpClass::Unspecified),
+            ("::1", IpClass::Loopback),
+            ("::1.2.3.4", IpClass::Reserved),
+            ("::ffff:1.2.3.4", IpClass::Global),
+            ("::ffff:192.168.1.1", IpClass::Private),
+            ("64:ff9b::1.2.3.4", IpClass::Global),
+            ("64:ff9b:1::1", IpClass::Reserved),
+            ("100::1", IpClass::Reserved),
+            ("2001::1", IpClass::Reserved),
+            ("2001:1::1", IpClass::Global),
+            ("2001:2::1", IpClass::Benchmarking),
+            ("2001:3::1", IpClass::Global),
+            ("2001:20::1", IpClass::Global),
+            ("2001:db8::1", IpClass::Documentation),
+            ("2002::1", IpClass::Global),
+            ("3fff::1", IpClass::Documentation),
+            ("3fff:1000::1", IpClass::Global),
+            ("fc00::1", IpClass::UniqueLocal),
+            ("fd12:3456::1", IpClass::UniqueLocal),
+            ("fe80::1", IpClass::LinkLocal),
+            ("fec0::1", IpClass::Reserved),
+            ("ff02::1", IpClass::Multicast),
+        ] {
+            assert_eq!(classify(ip.parse().unwrap()), class, "{ip}");
+        }
+    }
+}
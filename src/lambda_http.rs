@@ -0,0 +1,121 @@
+//! [`lambda_http`](https://docs.rs/lambda_http) request context extractor
+//!
+//! Behind API Gateway and Lambda function URLs the client IP is provided by
+//! AWS in the request context rather than in headers:
+//!
+//! ```
+//! use lambda_http::{Body, Error, Request, Response};
+//!
+//! async fn handler(request: Request) -> Result<Response<Body>, Error> {
+//!     let ip = client_ip::lambda_http::source_ip(&request)?;
+//!     Ok(Response::new(ip.to_string().into()))
+//! }
+//! ```
+
+use std::net::IpAddr;
+
+use http::Request;
+use lambda_http::{RequestExt, request::RequestContext};
+
+use crate::{Error, Result};
+
+/// Extracts client IP of a Lambda request
+///
+/// | Event                          | Source                                 |
+/// | ------------------------------ | -------------------------------------- |
+/// | API Gateway REST API (v1)      | `requestContext.identity.sourceIp`     |
+/// | API Gateway HTTP API (v2)      | `requestContext.http.sourceIp`         |
+/// | Lambda function URL            | `requestContext.http.sourceIp`         |
+/// | Application Load Balancer      | [`rightmost_x_forwarded_for`]          |
+///
+/// [`rightmost_x_forwarded_for`]: crate::rightmost_x_forwarded_for
+pub fn source_ip<B>(request: &Request<B>) -> Result<IpAddr> {
+    let source_ip = match request.request_context_ref() {
+        Some(RequestContext::ApiGatewayV1(context)) => context.identity.source_ip.as_deref(),
+        Some(RequestContext::ApiGatewayV2(context)) => context.http.source_ip.as_deref(),
+        Some(RequestContext::Alb(_)) => return crate::rightmost_x_forwarded_for(request.headers()),
+        _ => None,
+    }
+    .ok_or(Error::LambdaNoSourceIp)?;
+
+    source_ip
+        .trim()
+        .parse()
+        .map_err(|_| Error::LambdaMalformedSourceIp {
+            source_ip: source_ip.to_owned(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderName;
+    use lambda_http::aws_lambda_events::{
+        alb::AlbTargetGroupRequestContext,
+        apigw::{ApiGatewayProxyRequestContext, ApiGatewayV2httpRequestContext},
+    };
+
+    use super::*;
+
+    fn request(context: Option<RequestContext>, x_forwarded_for: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder();
+        if let Some(value) = x_forwarded_for {
+            builder = builder.header("x-forwarded-for", value);
+        }
+        let request = builder.body(()).unwrap();
+        match context {
+            Some(context) => request.with_request_context(context),
+            None => request,
+        }
+    }
+
+    fn v1(source_ip: Option<&str>) -> Option<RequestContext> {
+        let mut context = ApiGatewayProxyRequestContext::default();
+        context.identity.source_ip = source_ip.map(Into::into);
+        Some(RequestContext::ApiGatewayV1(context))
+    }
+
+    fn v2(source_ip: Option<&str>) -> Option<RequestContext> {
+        let mut context = ApiGatewayV2httpRequestContext::default();
+        context.http.source_ip = source_ip.map(Into::into);
+        Some(RequestContext::ApiGatewayV2(context))
+    }
+
+    #[test]
+    fn test_source_ip() {
+        let alb = Some(RequestContext::Alb(AlbTargetGroupRequestContext::default()));
+
+        assert_eq!(
+            source_ip(&request(None, Some("1.2.3.4"))).unwrap_err(),
+            Error::LambdaNoSourceIp
+        );
+        assert_eq!(
+            source_ip(&request(v1(None), None)).unwrap_err(),
+            Error::LambdaNoSourceIp
+        );
+        assert_eq!(
+            source_ip(&request(v2(Some("foo")), None)).unwrap_err(),
+            Error::LambdaMalformedSourceIp {
+                source_ip: "foo".into()
+            }
+        );
+        assert_eq!(
+            source_ip(&request(alb.clone(), None)).unwrap_err(),
+            Error::AbsentHeader {
+                header_name: HeaderName::from_static("x-forwarded-for")
+            }
+        );
+
+        assert_eq!(
+            source_ip(&request(v1(Some("1.2.3.4")), Some("5.6.7.8"))).unwrap(),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            source_ip(&request(v2(Some("2001:db8::1")), Some("5.6.7.8"))).unwrap(),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            source_ip(&request(alb, Some("5.6.7.8, 1.2.3.4"))).unwrap(),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -1,19 +1,72 @@
 #![doc = include_str!("../README.md")]
 use std::net::IpAddr;
 
+pub use canonical::{CanonicalIp, Canonicalization, Provenance};
+pub use config::ClientIpConfig;
 pub use error::Error;
-use http::{HeaderMap, HeaderName};
-
-type Result<T> = std::result::Result<T, Error>;
+use explain::{Selection, Tracer, Verdict};
+pub use header_access::HeaderAccess;
+use http::HeaderName;
+pub use ip_class::{IpClass, classify};
+pub use rejection::ClientIpRejection;
+pub use source::{ClientIp, ClientIpSource, InvalidClientIpSource};
+pub use trusted_proxies::{InvalidIpNetwork, IpNetwork, TrustedProxies};
+
+#[cfg(feature = "actix-web")]
+pub mod actix_web;
+#[cfg(feature = "axum")]
+pub mod axum;
+mod canonical;
+pub mod cgi;
+mod config;
+pub mod consistency;
+pub mod explain;
+pub mod forwarding;
+mod header_access;
+mod header_names;
+#[cfg(feature = "hyper")]
+pub mod hyper;
+mod ip_class;
+#[cfg(feature = "lambda_http")]
+pub mod lambda_http;
+#[cfg(feature = "pingora")]
+pub mod pingora;
+#[cfg(feature = "poem")]
+pub mod poem;
+pub mod raw;
+mod rejection;
+#[cfg(feature = "rocket")]
+pub mod rocket;
+#[cfg(feature = "salvo")]
+pub mod salvo;
+pub mod sanitize;
+pub mod shadow;
+mod source;
+pub mod spoofable;
+#[cfg(feature = "tonic")]
+pub mod tonic;
+pub mod topology;
+#[cfg(feature = "tower")]
+pub mod tower;
+mod trusted_proxies;
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
 
 /// Extracts client IP from `CF-Connecting-IP` (Cloudflare) header
-pub fn cf_connecting_ip(header_map: &HeaderMap) -> Result<IpAddr> {
-    ip_from_single_header(header_map, &HeaderName::from_static("cf-connecting-ip"))
+pub fn cf_connecting_ip(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    ip_from_single_header(header_map, &header_names::CF_CONNECTING_IP, &mut ())
 }
 
 /// Extracts client IP from `CloudFront-Viewer-Address` (AWS CloudFront) header
-pub fn cloudfront_viewer_address(header_map: &HeaderMap) -> Result<IpAddr> {
-    const HEADER_NAME: HeaderName = HeaderName::from_static("cloudfront-viewer-address");
+pub fn cloudfront_viewer_address(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    cloudfront_viewer_address_traced(header_map, &mut ())
+}
+
+fn cloudfront_viewer_address_traced(
+    header_map: &impl HeaderAccess,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    const HEADER_NAME: HeaderName = header_names::CLOUDFRONT_VIEWER_ADDRESS;
 
     fn ip_from_header_value(header_value: &str) -> Result<IpAddr> {
         // Spec: https://docs.aws.amazon.com/AmazonCloudFront/latest/DeveloperGuide/adding-cloudfront-headers.html#cloudfront-headers-viewer-location
@@ -37,103 +90,335 @@ pub fn cloudfront_viewer_address(header_map: &HeaderMap) -> Result<IpAddr> {
             })
     }
 
+    tracer.header(header_map, &HEADER_NAME, Selection::Last);
     let header_value = AsciiHeaderValue::of_last_header(header_map, &HEADER_NAME)?;
     ip_from_header_value(header_value.0)
 }
 
+/// Client IP determined according to Envoy's trusted client address rules,
+/// see [`envoy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvoyClientIp {
+    /// The trusted client address
+    pub ip: IpAddr,
+    /// Envoy considered the request internal and set `X-Envoy-Internal: true`
+    pub internal: bool,
+}
+
+/// Extracts client IP the way Envoy determines the [trusted client address]
+///
+/// `xff_num_trusted_hops` must match the Envoy's [`xff_num_trusted_hops`]
+/// setting. With `use_remote_address: true` Envoy appends the downstream
+/// address to `X-Forwarded-For`, with `use_remote_address: false` it trusts the
+/// last address in the header as is. Either way, as seen by the upstream
+/// service, the client is the `xff_num_trusted_hops`-th address (zero-based)
+/// counting from the right of the `X-Forwarded-For` header.
+///
+/// Envoy strips `X-Envoy-Internal` from requests it doesn't consider internal,
+/// so its presence is reported as [`EnvoyClientIp::internal`].
+///
+/// [trusted client address]: https://www.envoyproxy.io/docs/envoy/latest/configuration/http/http_conn_man/headers#x-forwarded-for
+/// [`xff_num_trusted_hops`]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/extensions/filters/network/http_connection_manager/v3/http_connection_manager.proto#envoy-v3-api-field-extensions-filters-network-http-connection-manager-v3-httpconnectionmanager-xff-num-trusted-hops
+pub fn envoy(header_map: &impl HeaderAccess, xff_num_trusted_hops: usize) -> Result<EnvoyClientIp> {
+    envoy_traced(header_map, xff_num_trusted_hops, &mut ())
+}
+
+fn envoy_traced(
+    header_map: &impl HeaderAccess,
+    xff_num_trusted_hops: usize,
+    tracer: &mut impl Tracer,
+) -> Result<EnvoyClientIp> {
+    const INTERNAL_HEADER_NAME: HeaderName = header_names::X_ENVOY_INTERNAL;
+
+    tracer.header(header_map, &INTERNAL_HEADER_NAME, Selection::Single);
+    let internal = match AsciiHeaderValue::of_single_header(header_map, &INTERNAL_HEADER_NAME) {
+        Ok(header_value) if header_value.0.trim() == "true" => true,
+        Ok(header_value) => {
+            return Err(Error::MalformedHeaderValue {
+                header_name: INTERNAL_HEADER_NAME,
+                header_value: header_value.0.to_owned(),
+            });
+        }
+        Err(Error::AbsentHeader { .. }) => false,
+        Err(e) => return Err(e),
+    };
+
+    let ip = nth_rightmost_ip(
+        header_map,
+        &header_names::X_FORWARDED_FOR,
+        xff_num_trusted_hops,
+        tracer,
+    )?;
+
+    Ok(EnvoyClientIp { ip, internal })
+}
+
 /// Extracts client IP from `Fly-Client-IP` (Fly.io) header
 ///
 /// When the extractor is run for health check path, provide required
 /// `Fly-Client-IP` header through [`services.http_checks.headers`](https://fly.io/docs/reference/configuration/#services-http_checks)
 /// or [`http_service.checks.headers`](https://fly.io/docs/reference/configuration/#services-http_checks)
-pub fn fly_client_ip(header_map: &HeaderMap) -> Result<IpAddr> {
-    ip_from_single_header(header_map, &HeaderName::from_static("fly-client-ip"))
+pub fn fly_client_ip(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    ip_from_single_header(header_map, &header_names::FLY_CLIENT_IP, &mut ())
+}
+
+/// Extracts client IP behind [ingress-nginx] from `X-Original-Forwarded-For`
+/// header
+///
+/// ingress-nginx overwrites `X-Forwarded-For` with the address of its peer and
+/// preserves the received value in `X-Original-Forwarded-For`. Every proxy in
+/// front of the ingress (e.g. a cloud load balancer) appends the address of
+/// its own peer, so with `trusted_proxies` such proxies the client is found
+/// after skipping `trusted_proxies - 1` entries from the right.
+///
+/// Without proxies in front of the ingress (`trusted_proxies == 0`) the client
+/// is the peer of ingress-nginx, so the rightmost `X-Forwarded-For` entry is
+/// used instead.
+///
+/// [ingress-nginx]: https://kubernetes.github.io/ingress-nginx/user-guide/nginx-configuration/configmap/
+pub fn ingress_nginx(header_map: &impl HeaderAccess, trusted_proxies: usize) -> Result<IpAddr> {
+    ingress_nginx_traced(header_map, trusted_proxies, &mut ())
+}
+
+fn ingress_nginx_traced(
+    header_map: &impl HeaderAccess,
+    trusted_proxies: usize,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    match trusted_proxies.checked_sub(1) {
+        Some(trusted_hops) => nth_rightmost_ip(
+            header_map,
+            &header_names::X_ORIGINAL_FORWARDED_FOR,
+            trusted_hops,
+            tracer,
+        ),
+        None => nth_rightmost_ip(header_map, &header_names::X_FORWARDED_FOR, 0, tracer),
+    }
 }
 
 #[cfg(feature = "forwarded-header")]
 /// Extracts the rightmost IP from `Forwarded` header
-pub fn rightmost_forwarded(header_map: &HeaderMap) -> Result<IpAddr> {
-    const HEADER_NAME: HeaderName = HeaderName::from_static("forwarded");
+pub fn rightmost_forwarded(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    rightmost_forwarded_resolved(header_map, &|_: &forwarding::ForwardedNode| None)
+}
 
-    fn ip_from_header_value(header_value: &str) -> Result<IpAddr> {
-        use forwarded_header_value::{ForwardedHeaderValue, Identifier};
+#[cfg(feature = "forwarded-header")]
+/// Extracts the rightmost IP from `Forwarded` header, resolving obfuscated and
+/// `unknown` identifiers with the resolver
+///
+/// Identifiers the resolver doesn't know fail the extraction the same way
+/// [`rightmost_forwarded`] does.
+pub fn rightmost_forwarded_resolved(
+    header_map: &impl HeaderAccess,
+    resolver: &impl forwarding::ForwardedNodeResolver,
+) -> Result<IpAddr> {
+    rightmost_forwarded_resolved_traced(header_map, resolver, &mut ())
+}
 
-        let stanza = ForwardedHeaderValue::from_forwarded(header_value)
-            .map_err(|_| Error::MalformedHeaderValue {
-                header_name: HEADER_NAME,
-                header_value: header_value.to_owned(),
-            })?
-            .into_iter()
-            .last()
-            .ok_or_else(|| Error::MalformedHeaderValue {
-                header_name: HEADER_NAME,
+#[cfg(feature = "forwarded-header")]
+fn rightmost_forwarded_resolved_traced(
+    header_map: &impl HeaderAccess,
+    resolver: &impl forwarding::ForwardedNodeResolver,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    rightmost_forwarded_element(header_map, tracer, |header_value, element| {
+        forwarded_for_ip(header_value, element, resolver)
+    })
+}
+
+#[cfg(feature = "forwarded-header")]
+/// Extracts the rightmost IP from `Forwarded` header if the rightmost element
+/// was added by one of the proxies
+///
+/// Proxies record the interface they received the request on in the `by`
+/// parameter, so a header forged by a client reaching the backend directly
+/// lacks a matching `by`. Proxies are identified by IPs, which match `by` with
+/// any port, socket addresses, or
+/// [obfuscated](forwarding::ForwardedNode::Obfuscated) tokens and names.
+pub fn rightmost_forwarded_by(
+    header_map: &impl HeaderAccess,
+    proxies: &[forwarding::ForwardedNode],
+) -> Result<IpAddr> {
+    rightmost_forwarded_by_traced(header_map, proxies, &mut ())
+}
+
+#[cfg(feature = "forwarded-header")]
+fn rightmost_forwarded_by_traced(
+    header_map: &impl HeaderAccess,
+    proxies: &[forwarding::ForwardedNode],
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    use crate::forwarding::ForwardedNode;
+
+    rightmost_forwarded_element(header_map, tracer, |header_value, element| {
+        let is_known = element.by.as_ref().is_some_and(|by| {
+            proxies.iter().any(|proxy| match (proxy, by) {
+                (ForwardedNode::Ip(ip), ForwardedNode::SocketAddr(addr)) => *ip == addr.ip(),
+                _ => proxy == by,
+            })
+        });
+        if !is_known {
+            return Err(Error::ForwardedUnknownBy {
                 header_value: header_value.to_owned(),
-            })?;
+            });
+        }
+        forwarded_for_ip(header_value, element, &|_: &ForwardedNode| None)
+    })
+}
+
+#[cfg(feature = "forwarded-header")]
+/// Parses the last `Forwarded` header and takes the IP from its rightmost
+/// element with `client_ip`
+fn rightmost_forwarded_element(
+    header_map: &impl HeaderAccess,
+    tracer: &mut impl Tracer,
+    client_ip: impl FnOnce(&str, &forwarding::ForwardedElement) -> Result<IpAddr>,
+) -> Result<IpAddr> {
+    use forwarded_header_value::ForwardedHeaderValue;
+
+    use crate::forwarding::ForwardedElement;
+
+    const HEADER_NAME: HeaderName = header_names::FORWARDED;
+
+    tracer.header(header_map, &HEADER_NAME, Selection::Last);
+    let header_value = AsciiHeaderValue::of_last_header(header_map, &HEADER_NAME)?.0;
+    let malformed = || Error::MalformedHeaderValue {
+        header_name: HEADER_NAME,
+        header_value: header_value.to_owned(),
+    };
+
+    let elements: Vec<_> = ForwardedHeaderValue::from_forwarded(header_value)
+        .map_err(|_| malformed())?
+        .into_iter()
+        .map(ForwardedElement::from_stanza)
+        .collect();
+    tracer.hops_of(&HEADER_NAME);
+    let mut hops = elements.iter().rev();
+    let element = hops.next().ok_or_else(malformed)?;
+
+    let result = client_ip(header_value, element);
+    tracer.hop(element, Verdict::of(&result));
+    for element in hops {
+        tracer.hop(element, Verdict::Ignored);
+    }
+    result
+}
 
-        let forwarded_for = stanza.forwarded_for.ok_or_else(|| Error::ForwardedNoFor {
+#[cfg(feature = "forwarded-header")]
+/// Takes the IP from the `for` parameter of a `Forwarded` element
+fn forwarded_for_ip(
+    header_value: &str,
+    element: &forwarding::ForwardedElement,
+    resolver: &impl forwarding::ForwardedNodeResolver,
+) -> Result<IpAddr> {
+    use crate::forwarding::ForwardedNode;
+
+    let for_node = element
+        .for_node
+        .as_ref()
+        .ok_or_else(|| Error::ForwardedNoFor {
             header_value: header_value.to_owned(),
         })?;
 
-        match forwarded_for {
-            Identifier::SocketAddr(a) => Ok(a.ip()),
-            Identifier::IpAddr(ip) => Ok(ip),
-            Identifier::String(_) => Err(Error::ForwardedObfuscated {
-                header_value: header_value.to_owned(),
-            }),
-            Identifier::Unknown => Err(Error::ForwardedUnknown {
-                header_value: header_value.to_owned(),
-            }),
+    match for_node {
+        ForwardedNode::SocketAddr(a) => Ok(a.ip()),
+        ForwardedNode::Ip(ip) => Ok(*ip),
+        ForwardedNode::Obfuscated(_) => {
+            resolver
+                .resolve(for_node)
+                .ok_or_else(|| Error::ForwardedObfuscated {
+                    header_value: header_value.to_owned(),
+                })
+        }
+        ForwardedNode::Unknown => {
+            resolver
+                .resolve(for_node)
+                .ok_or_else(|| Error::ForwardedUnknown {
+                    header_value: header_value.to_owned(),
+                })
         }
     }
-
-    let header_value = AsciiHeaderValue::of_last_header(header_map, &HEADER_NAME)?;
-    ip_from_header_value(header_value.0)
 }
 
 /// Extracts the rightmost IP address from the comma-separated list in the value
 /// of the last `X-Forwarded-For` header.
-pub fn rightmost_x_forwarded_for(header_map: &HeaderMap) -> Result<IpAddr> {
-    const HEADER_NAME: HeaderName = HeaderName::from_static("x-forwarded-for");
+pub fn rightmost_x_forwarded_for(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    rightmost_x_forwarded_for_traced(header_map, &mut ())
+}
 
-    fn ip_from_header_value(header_value: &str) -> Result<IpAddr> {
-        header_value
-            .split(',')
-            .next_back()
-            .ok_or_else(|| Error::MalformedHeaderValue {
-                header_name: HEADER_NAME,
-                header_value: header_value.to_owned(),
-            })?
-            .trim()
-            .parse::<IpAddr>()
-            .map_err(|_| Error::MalformedHeaderValue {
-                header_name: HEADER_NAME,
-                header_value: header_value.to_owned(),
-            })
+fn rightmost_x_forwarded_for_traced(
+    header_map: &impl HeaderAccess,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    const HEADER_NAME: HeaderName = header_names::X_FORWARDED_FOR;
+
+    tracer.header(header_map, &HEADER_NAME, Selection::Last);
+    let header_value = AsciiHeaderValue::of_last_header(header_map, &HEADER_NAME)?.0;
+    let malformed = || Error::MalformedHeaderValue {
+        header_name: HEADER_NAME,
+        header_value: header_value.to_owned(),
+    };
+
+    tracer.hops_of(&HEADER_NAME);
+    let mut hops = header_value.split(',').map(str::trim).rev();
+    let entry = hops.next().ok_or_else(malformed)?;
+    let result = entry.parse::<IpAddr>().map_err(|_| malformed());
+    tracer.hop(entry, Verdict::of(&result));
+    for entry in hops {
+        tracer.hop(entry, Verdict::Ignored);
     }
+    result
+}
 
-    let header_value = AsciiHeaderValue::of_last_header(header_map, &HEADER_NAME)?;
-    ip_from_header_value(header_value.0)
+/// Extracts client IP behind [Traefik] from `X-Forwarded-For` header
+///
+/// `trusted_proxies` is the number of proxies in front of Traefik listed in its
+/// [`forwardedHeaders.trustedIPs`]. Traefik drops `X-Forwarded-For` received
+/// from untrusted peers and appends the address of its peer, so each trusted
+/// proxy adds exactly one hop to skip from the right.
+///
+/// [Traefik]: https://doc.traefik.io/traefik/routing/entrypoints/#forwarded-headers
+/// [`forwardedHeaders.trustedIPs`]: https://doc.traefik.io/traefik/routing/entrypoints/#forwarded-headers
+pub fn traefik(header_map: &impl HeaderAccess, trusted_proxies: usize) -> Result<IpAddr> {
+    nth_rightmost_ip(
+        header_map,
+        &header_names::X_FORWARDED_FOR,
+        trusted_proxies,
+        &mut (),
+    )
 }
 
 /// Extracts client IP from `True-Client-IP` (Akamai, Cloudflare) header
-pub fn true_client_ip(header_map: &HeaderMap) -> Result<IpAddr> {
-    ip_from_single_header(header_map, &HeaderName::from_static("true-client-ip"))
+pub fn true_client_ip(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    ip_from_single_header(header_map, &header_names::TRUE_CLIENT_IP, &mut ())
 }
 
 /// Extracts client IP from `X-Envoy-External-Address` header
-pub fn x_envoy_external_address(header_map: &HeaderMap) -> Result<IpAddr> {
-    ip_from_single_header(
-        header_map,
-        &HeaderName::from_static("x-envoy-external-address"),
-    )
+pub fn x_envoy_external_address(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    ip_from_single_header(header_map, &header_names::X_ENVOY_EXTERNAL_ADDRESS, &mut ())
 }
 
 /// Extracts client IP from `X-Real-Ip` (Nginx) header
-pub fn x_real_ip(header_map: &HeaderMap) -> Result<IpAddr> {
-    ip_from_single_header(header_map, &HeaderName::from_static("x-real-ip"))
+pub fn x_real_ip(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    ip_from_single_header(header_map, &header_names::X_REAL_IP, &mut ())
 }
 
-/// A [`http::HeaderValue`] converted to string and ensured to be valid ASCII
+/// Rejects IPs that aren't globally routable, see [`IpClass`]
+///
+/// Chain it after any extractor:
+///
+/// ```
+/// # let header_map = http::HeaderMap::new();
+/// let ip = client_ip::x_real_ip(&header_map).and_then(client_ip::require_global);
+/// ```
+pub fn require_global(ip: IpAddr) -> Result<IpAddr> {
+    match classify(ip) {
+        IpClass::Global => Ok(ip),
+        class => Err(Error::NonGlobalIp { ip, class }),
+    }
+}
+
+/// A raw header value converted to string and ensured to be valid ASCII
 #[derive(Debug)]
 struct AsciiHeaderValue<'a>(&'a str);
 
@@ -141,8 +426,11 @@ impl<'a> AsciiHeaderValue<'a> {
     /// Returns value of a header that must occur only once. Multiple
     /// occurrences of the header are considered a critical proxy configuration
     /// error.
-    fn of_single_header(header_map: &'a HeaderMap, header_name: &HeaderName) -> Result<Self> {
-        let mut iter = header_map.get_all(header_name).into_iter();
+    fn of_single_header(
+        header_map: &'a impl HeaderAccess,
+        header_name: &HeaderName,
+    ) -> Result<Self> {
+        let mut iter = header_map.header_values(header_name);
 
         let Some(header_value) = iter.next() else {
             return Err(Error::AbsentHeader {
@@ -156,28 +444,50 @@ impl<'a> AsciiHeaderValue<'a> {
             });
         }
 
-        header_value
-            .to_str()
-            .map_err(|_| Error::NonAsciiHeaderValue {
-                header_name: header_name.to_owned(),
-            })
-            .map(Self)
+        Self::from_bytes(header_value, header_name)
     }
 
     /// Returns a value of the last occurring header.
-    fn of_last_header(header_map: &'a HeaderMap, header_name: &HeaderName) -> Result<Self> {
-        header_map
-            .get_all(header_name)
-            .into_iter()
-            .next_back()
+    fn of_last_header(header_map: &'a impl HeaderAccess, header_name: &HeaderName) -> Result<Self> {
+        let header_value = header_map
+            .header_values(header_name)
+            .last()
             .ok_or_else(|| Error::AbsentHeader {
                 header_name: header_name.to_owned(),
-            })?
-            .to_str()
-            .map_err(|_| Error::NonAsciiHeaderValue {
+            })?;
+        Self::from_bytes(header_value, header_name)
+    }
+
+    /// Returns values of all occurrences of the header in order.
+    fn of_all_headers(
+        header_map: &'a impl HeaderAccess,
+        header_name: &HeaderName,
+    ) -> Result<Vec<Self>> {
+        let header_values = header_map
+            .header_values(header_name)
+            .map(|header_value| Self::from_bytes(header_value, header_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        if header_values.is_empty() {
+            return Err(Error::AbsentHeader {
                 header_name: header_name.to_owned(),
-            })
+            });
+        }
+        Ok(header_values)
+    }
+
+    /// Ensures the raw value contains only visible ASCII characters, the same
+    /// way as [`http::HeaderValue::to_str`] does.
+    fn from_bytes(header_value: &'a [u8], header_name: &HeaderName) -> Result<Self> {
+        header_value
+            .iter()
+            .all(|&b| b == b'\t' || (32..127).contains(&b))
+            .then(|| std::str::from_utf8(header_value).ok())
+            .flatten()
             .map(Self)
+            .ok_or_else(|| Error::NonAsciiHeaderValue {
+                header_name: header_name.to_owned(),
+            })
     }
 
     /// Tries to parse the whole value as an IP.
@@ -194,17 +504,75 @@ impl<'a> AsciiHeaderValue<'a> {
 
 /// Parses an IP from a header that occurs only once. Multiple
 /// occurrences of the header are considered a proxy configuration error.
-fn ip_from_single_header(header_map: &HeaderMap, header_name: &HeaderName) -> Result<IpAddr> {
+fn ip_from_single_header(
+    header_map: &impl HeaderAccess,
+    header_name: &HeaderName,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    tracer.header(header_map, header_name, Selection::Single);
     AsciiHeaderValue::of_single_header(header_map, header_name)?.parse_ip(header_name)
 }
 
+/// Parses an IP from a comma-separated list header skipping `trusted_hops`
+/// entries from the right. Multiple occurrences of the header are treated as a
+/// single list.
+fn nth_rightmost_ip(
+    header_map: &impl HeaderAccess,
+    header_name: &HeaderName,
+    trusted_hops: usize,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    tracer.header(header_map, header_name, Selection::All);
+    let entries = list_header_entries(header_map, header_name)?;
+
+    tracer.hops_of(header_name);
+    let mut hops = entries.into_iter().rev();
+    for (_, entry) in hops.by_ref().take(trusted_hops) {
+        tracer.hop(entry, Verdict::Trusted);
+    }
+    let (header_value, entry) = hops.next().ok_or_else(|| Error::InsufficientHops {
+        header_name: header_name.to_owned(),
+        trusted_hops,
+    })?;
+
+    let result = entry.parse().map_err(|_| Error::MalformedHeaderValue {
+        header_name: header_name.to_owned(),
+        header_value: header_value.to_owned(),
+    });
+    tracer.hop(entry, Verdict::of(&result));
+    for (_, entry) in hops {
+        tracer.hop(entry, Verdict::Ignored);
+    }
+    result
+}
+
+/// Splits all occurrences of a comma-separated list header into trimmed
+/// entries in order of appearance. Each entry is paired with the header value
+/// it comes from.
+pub(crate) fn list_header_entries<'a>(
+    header_map: &'a impl HeaderAccess,
+    header_name: &HeaderName,
+) -> Result<Vec<(&'a str, &'a str)>> {
+    Ok(AsciiHeaderValue::of_all_headers(header_map, header_name)?
+        .into_iter()
+        .flat_map(|header_value| {
+            header_value
+                .0
+                .split(',')
+                .map(move |entry| (header_value.0, entry.trim()))
+        })
+        .collect())
+}
+
 mod error {
-    use std::fmt;
+    use std::{fmt, net::IpAddr};
 
     use http::HeaderName;
 
+    use crate::{ClientIpSource, IpClass};
+
     /// Errors that can occur during IP extraction
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Error {
         /// The IP-related header is missing
         AbsentHeader {
@@ -233,6 +601,55 @@ mod error {
             /// Header name
             header_name: HeaderName,
         },
+        /// A comma-separated list header contains no entries left of the
+        /// trusted hops
+        InsufficientHops {
+            /// Header name
+            header_name: HeaderName,
+            /// Number of trusted hops
+            trusted_hops: usize,
+        },
+        /// A comma-separated list header contains no globally routable IP
+        ///
+        /// Unlike [`NonGlobalIp`](Self::NonGlobalIp), which rejects a single
+        /// extracted IP, it's returned by extractors scanning the whole list
+        /// for a globally routable entry.
+        NoGlobalIpInList {
+            /// Header name
+            header_name: HeaderName,
+        },
+        /// Two sources yield different IPs for the same request
+        InconsistentSources {
+            /// The disagreeing sources
+            sources: (ClientIpSource, ClientIpSource),
+            /// IPs yielded by the sources
+            ips: (IpAddr, IpAddr),
+        },
+        /// A source yields an IP while the header of its paired source is
+        /// absent
+        UnpairedSource {
+            /// The source yielding an IP
+            source: ClientIpSource,
+            /// The source with the absent header
+            absent: ClientIpSource,
+        },
+        /// The extracted IP isn't globally routable, see
+        /// [`require_global`](crate::require_global)
+        NonGlobalIp {
+            /// The extracted IP
+            ip: IpAddr,
+            /// Class of the IP
+            class: IpClass,
+        },
+        #[cfg(feature = "lambda_http")]
+        /// Lambda event carries no request context with the source IP
+        LambdaNoSourceIp,
+        #[cfg(feature = "lambda_http")]
+        /// Lambda request context source IP has an unexpected format
+        LambdaMalformedSourceIp {
+            /// Source IP
+            source_ip: String,
+        },
         #[cfg(feature = "forwarded-header")]
         /// Forwarded header doesn't contain `for` directive
         ForwardedNoFor {
@@ -251,6 +668,13 @@ mod error {
             /// Header value
             header_value: String,
         },
+        #[cfg(feature = "forwarded-header")]
+        /// The `by` parameter of the rightmost `Forwarded` element is missing
+        /// or doesn't identify a known proxy
+        ForwardedUnknownBy {
+            /// Header value
+            header_value: String,
+        },
     }
 
     impl fmt::Display for Error {
@@ -274,6 +698,38 @@ mod error {
                     f,
                     "Multiple occurrences of the header aren't allowed: {header_name}"
                 ),
+                Self::InsufficientHops {
+                    header_name,
+                    trusted_hops,
+                } => write!(
+                    f,
+                    "Header `{header_name}` has no entries beyond {trusted_hops} trusted hops",
+                ),
+                Self::NoGlobalIpInList { header_name } => {
+                    write!(f, "Header `{header_name}` contains no globally routable IP")
+                }
+                Self::InconsistentSources {
+                    sources: (left, right),
+                    ips: (left_ip, right_ip),
+                } => write!(
+                    f,
+                    "Client IP sources disagree: {left} yields {left_ip}, {right} yields {right_ip}"
+                ),
+                Self::UnpairedSource { source, absent } => write!(
+                    f,
+                    "Client IP source {source} yields an IP, but the header of {absent} is absent"
+                ),
+                Self::NonGlobalIp { ip, class } => {
+                    write!(f, "IP isn't globally routable: {ip} ({class:?})")
+                }
+                #[cfg(feature = "lambda_http")]
+                Self::LambdaNoSourceIp => {
+                    write!(f, "Lambda request context doesn't contain source IP")
+                }
+                #[cfg(feature = "lambda_http")]
+                Self::LambdaMalformedSourceIp { source_ip } => {
+                    write!(f, "Malformed Lambda request context source IP: {source_ip}")
+                }
                 #[cfg(feature = "forwarded-header")]
                 Self::ForwardedNoFor { header_value } => write!(
                     f,
@@ -289,6 +745,11 @@ mod error {
                     f,
                     "`Forwarded` header contains unknown identifier: {header_value}",
                 ),
+                #[cfg(feature = "forwarded-header")]
+                Self::ForwardedUnknownBy { header_value } => write!(
+                    f,
+                    "`Forwarded` header isn't added by a known proxy: {header_value}",
+                ),
             }
         }
     }
@@ -298,6 +759,8 @@ mod error {
 
 #[cfg(test)]
 mod tests {
+    use http::HeaderMap;
+
     use super::*;
 
     const VALID_IPV4: &str = "1.2.3.4";
@@ -469,6 +932,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_envoy() {
+        // Examples from https://www.envoyproxy.io/docs/envoy/latest/configuration/http/http_conn_man/headers#x-forwarded-for
+        // as seen by the upstream service
+        let xff = "x-forwarded-for";
+        let forwarded_by_edge = "203.0.113.128, 203.0.113.10, 203.0.113.1, 192.0.2.5";
+
+        assert_eq!(
+            envoy(&headers([]), 0).unwrap_err(),
+            Error::AbsentHeader {
+                header_name: HeaderName::from_static(xff)
+            }
+        );
+        assert_eq!(
+            envoy(&headers([(xff, "192.0.2.5")]), 1).unwrap_err(),
+            Error::InsufficientHops {
+                header_name: HeaderName::from_static(xff),
+                trusted_hops: 1,
+            }
+        );
+        assert_eq!(
+            envoy(&headers([(xff, "foo, 192.0.2.5")]), 1).unwrap_err(),
+            Error::MalformedHeaderValue {
+                header_name: HeaderName::from_static(xff),
+                header_value: "foo, 192.0.2.5".into(),
+            }
+        );
+        assert_eq!(
+            envoy(
+                &headers([(xff, "10.20.30.40"), ("x-envoy-internal", "false")]),
+                0
+            )
+            .unwrap_err(),
+            Error::MalformedHeaderValue {
+                header_name: HeaderName::from_static("x-envoy-internal"),
+                header_value: "false".into(),
+            }
+        );
+
+        // Examples 1 and 2: edge proxy, no trusted proxies in front of it
+        assert_eq!(
+            envoy(&headers([(xff, forwarded_by_edge)]), 0).unwrap(),
+            EnvoyClientIp {
+                ip: "192.0.2.5".parse().unwrap(),
+                internal: false,
+            }
+        );
+
+        // Examples 3 and 4: two trusted external proxies in front of the edge
+        assert_eq!(
+            envoy(&headers([(xff, forwarded_by_edge)]), 2).unwrap(),
+            EnvoyClientIp {
+                ip: "203.0.113.10".parse().unwrap(),
+                internal: false,
+            }
+        );
+        assert_eq!(
+            envoy(
+                &headers([
+                    (xff, "203.0.113.128, 203.0.113.10"),
+                    (xff, "203.0.113.1, 192.0.2.5")
+                ]),
+                2
+            )
+            .unwrap()
+            .ip,
+            "203.0.113.10".parse::<IpAddr>().unwrap(),
+            "multiple headers"
+        );
+
+        // Example 6: internal request proxied by another Envoy
+        assert_eq!(
+            envoy(
+                &headers([(xff, "10.20.30.40"), ("x-envoy-internal", "true")]),
+                0
+            )
+            .unwrap(),
+            EnvoyClientIp {
+                ip: "10.20.30.40".parse().unwrap(),
+                internal: true,
+            }
+        );
+    }
+
     #[test]
     fn test_fly_client_ip() {
         let header = "fly-client-ip";
@@ -503,6 +1050,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ingress_nginx() {
+        let original = "x-original-forwarded-for";
+        let xff = "x-forwarded-for";
+
+        assert_eq!(
+            ingress_nginx(&headers([(xff, "10.0.0.1")]), 1).unwrap_err(),
+            Error::AbsentHeader {
+                header_name: HeaderName::from_static(original)
+            }
+        );
+        assert_eq!(
+            ingress_nginx(&headers([(original, "ы")]), 1).unwrap_err(),
+            Error::NonAsciiHeaderValue {
+                header_name: HeaderName::from_static(original)
+            }
+        );
+        assert_eq!(
+            ingress_nginx(&headers([(original, VALID_IPV4)]), 2).unwrap_err(),
+            Error::InsufficientHops {
+                header_name: HeaderName::from_static(original),
+                trusted_hops: 1,
+            }
+        );
+
+        assert_eq!(
+            ingress_nginx(&headers([(xff, VALID_IPV4)]), 0).unwrap(),
+            VALID_IPV4.parse::<IpAddr>().unwrap(),
+            "no proxies in front of the ingress"
+        );
+        assert_eq!(
+            ingress_nginx(
+                &headers([
+                    (original, format!("foo, {VALID_IPV6}").as_ref()),
+                    (xff, "10.0.0.1")
+                ]),
+                1
+            )
+            .unwrap(),
+            VALID_IPV6.parse::<IpAddr>().unwrap(),
+            "cloud load balancer"
+        );
+        assert_eq!(
+            ingress_nginx(
+                &headers([
+                    (original, format!("foo, {VALID_IPV4}, 10.0.0.2").as_ref()),
+                    (xff, "10.0.0.1")
+                ]),
+                2
+            )
+            .unwrap(),
+            VALID_IPV4.parse::<IpAddr>().unwrap(),
+            "CDN and cloud load balancer"
+        );
+    }
+
     #[cfg(feature = "forwarded-header")]
     #[test]
     fn test_rightmost_forwarded() {
@@ -582,6 +1185,102 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "forwarded-header")]
+    #[test]
+    fn test_rightmost_forwarded_by() {
+        use crate::forwarding::ForwardedNode;
+
+        let header = "forwarded";
+        let proxies = [
+            ForwardedNode::Ip("10.0.0.1".parse().unwrap()),
+            ForwardedNode::SocketAddr("10.0.0.2:80".parse().unwrap()),
+            ForwardedNode::Obfuscated("_proxy".into()),
+        ];
+
+        for header_value in [
+            "for=1.2.3.4;by=10.0.0.1",
+            "for=1.2.3.4;by=\"10.0.0.1:8080\"",
+            "for=1.2.3.4;by=\"10.0.0.2:80\"",
+            "for=6.6.6.6;by=10.0.0.9, for=1.2.3.4;by=_proxy",
+        ] {
+            assert_eq!(
+                rightmost_forwarded_by(&headers([(header, header_value)]), &proxies),
+                Ok(VALID_IPV4.parse().unwrap()),
+                "{header_value}"
+            );
+        }
+
+        for header_value in [
+            "for=1.2.3.4",
+            "for=1.2.3.4;by=10.0.0.9",
+            "for=1.2.3.4;by=\"10.0.0.2:8080\"",
+            "for=1.2.3.4;by=_other",
+            "for=1.2.3.4;by=unknown",
+            "for=1.2.3.4;by=_proxy, for=6.6.6.6",
+        ] {
+            assert_eq!(
+                rightmost_forwarded_by(&headers([(header, header_value)]), &proxies),
+                Err(Error::ForwardedUnknownBy {
+                    header_value: header_value.into()
+                }),
+                "{header_value}"
+            );
+        }
+
+        assert_eq!(
+            rightmost_forwarded_by(&headers([(header, "by=10.0.0.1")]), &proxies),
+            Err(Error::ForwardedNoFor {
+                header_value: "by=10.0.0.1".into()
+            })
+        );
+    }
+
+    #[cfg(feature = "forwarded-header")]
+    #[test]
+    fn test_rightmost_forwarded_resolved() {
+        use std::collections::HashMap;
+
+        use crate::forwarding::ForwardedNode;
+
+        let header = "forwarded";
+        let table = HashMap::from([("_foo".to_owned(), VALID_IPV4.parse().unwrap())]);
+
+        assert_eq!(
+            rightmost_forwarded_resolved(&headers([(header, "for=_foo")]), &table).unwrap(),
+            VALID_IPV4.parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            rightmost_forwarded_resolved(&headers([(header, "for=_bar")]), &table).unwrap_err(),
+            Error::ForwardedObfuscated {
+                header_value: "for=_bar".into(),
+            }
+        );
+        assert_eq!(
+            rightmost_forwarded_resolved(&headers([(header, "for=unknown")]), &table).unwrap_err(),
+            Error::ForwardedUnknown {
+                header_value: "for=unknown".into(),
+            }
+        );
+
+        let resolver = |node: &ForwardedNode| match node {
+            ForwardedNode::Unknown => VALID_IPV6.parse().ok(),
+            _ => None,
+        };
+        assert_eq!(
+            rightmost_forwarded_resolved(&headers([(header, "for=unknown")]), &resolver).unwrap(),
+            VALID_IPV6.parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            rightmost_forwarded_resolved(
+                &headers([(header, format!("for={VALID_IPV4}").as_ref())]),
+                &resolver
+            )
+            .unwrap(),
+            VALID_IPV4.parse::<IpAddr>().unwrap(),
+            "IPs aren't resolved"
+        );
+    }
+
     #[test]
     fn test_rightmost_x_forwarded_for() {
         let header = "x-forwarded-for";
@@ -617,6 +1316,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_traefik() {
+        let header = "x-forwarded-for";
+
+        assert_eq!(
+            traefik(&headers([]), 0).unwrap_err(),
+            Error::AbsentHeader {
+                header_name: HeaderName::from_static(header)
+            }
+        );
+        assert_eq!(
+            traefik(&headers([(header, VALID_IPV4)]), 1).unwrap_err(),
+            Error::InsufficientHops {
+                header_name: HeaderName::from_static(header),
+                trusted_hops: 1,
+            }
+        );
+
+        assert_eq!(
+            traefik(
+                &headers([(header, format!("foo, {VALID_IPV4}").as_ref())]),
+                0
+            )
+            .unwrap(),
+            VALID_IPV4.parse::<IpAddr>().unwrap(),
+            "no trusted proxies"
+        );
+        assert_eq!(
+            traefik(
+                &headers([(header, format!("foo, {VALID_IPV6}, 10.0.0.1").as_ref())]),
+                1
+            )
+            .unwrap(),
+            VALID_IPV6.parse::<IpAddr>().unwrap(),
+            "trusted cloud load balancer"
+        );
+    }
+
+    #[test]
+    fn test_require_global() {
+        assert_eq!(
+            require_global(VALID_IPV4.parse().unwrap()).unwrap(),
+            VALID_IPV4.parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            require_global("10.0.0.1".parse().unwrap()).unwrap_err(),
+            Error::NonGlobalIp {
+                ip: "10.0.0.1".parse().unwrap(),
+                class: IpClass::Private,
+            }
+        );
+        assert_eq!(
+            x_real_ip(&headers([("x-real-ip", "100.64.0.1")]))
+                .and_then(require_global)
+                .unwrap_err(),
+            Error::NonGlobalIp {
+                ip: "100.64.0.1".parse().unwrap(),
+                class: IpClass::SharedCgnat,
+            }
+        );
+    }
+
     #[test]
     fn test_true_client_ip() {
         let header = "true-client-ip";
@@ -0,0 +1,254 @@
+//! [`pingora`](https://docs.rs/pingora) proxy integration
+//!
+//! [`session_client_ip`] resolves the client IP of a downstream session with a
+//! [`ClientIpConfig`] and [`set_forwarded_headers`] sanitizes IP headers of
+//! the upstream request according to a [`SanitizePolicy`], so upstream
+//! services can't be fooled by headers the client sent:
+//!
+//! ```
+//! use client_ip::{
+//!     ClientIpConfig, ClientIpSource,
+//!     pingora::{peer_addr, session_client_ip, set_forwarded_headers},
+//!     sanitize::SanitizePolicy,
+//! };
+//! use pingora_core::protocols::http::ServerSession;
+//! use pingora_error::{
+//!     Error,
+//!     ErrorType::{HTTPStatus, InternalError},
+//! };
+//! use pingora_http::RequestHeader;
+//!
+//! let config = ClientIpConfig::new(ClientIpSource::CfConnectingIp)
+//!     .trusted_proxies(["173.245.48.0/20".parse().unwrap()].into_iter().collect());
+//!
+//! // Called from `ProxyHttp::upstream_request_filter`, `pingora_proxy::Session`
+//! // dereferences to `ServerSession`
+//! fn forward(
+//!     config: &ClientIpConfig,
+//!     session: &ServerSession,
+//!     upstream_request: &mut RequestHeader,
+//! ) -> pingora_error::Result<()> {
+//!     let client_ip = session_client_ip(config, session)
+//!         .map_err(|e| Error::explain(HTTPStatus(e.status().as_u16()), e.to_string()))?;
+//!     let peer_addr =
+//!         peer_addr(session).ok_or_else(|| Error::explain(InternalError, "no peer address"))?;
+//!     set_forwarded_headers(
+//!         upstream_request,
+//!         peer_addr,
+//!         client_ip.0,
+//!         SanitizePolicy::Append,
+//!     )
+//! }
+//! ```
+
+use std::net::{IpAddr, SocketAddr};
+
+use pingora_core::protocols::http::ServerSession;
+use pingora_http::RequestHeader;
+
+use crate::{
+    ClientIp, ClientIpConfig, ClientIpRejection,
+    sanitize::{IP_HEADER_NAMES, SanitizePolicy, sanitize},
+};
+
+/// Extracts the client IP of a downstream session, taking the peer IP from
+/// its connection
+pub fn session_client_ip(
+    config: &ClientIpConfig,
+    session: &ServerSession,
+) -> Result<ClientIp, ClientIpRejection> {
+    let peer_ip = peer_addr(session).map(|addr| addr.ip());
+    client_ip(config, session.req_header(), peer_ip)
+}
+
+/// Returns the address of the downstream connection, `None` if it isn't an
+/// IP socket
+pub fn peer_addr(session: &ServerSession) -> Option<SocketAddr> {
+    session
+        .client_addr()
+        .and_then(|addr| addr.as_inet())
+        .copied()
+}
+
+/// Extracts the client IP of a downstream request
+///
+/// The `peer_ip` is the IP of the downstream connection, it's only required
+/// with [trusted proxies](ClientIpConfig::trusted_proxies) set.
+pub fn client_ip(
+    config: &ClientIpConfig,
+    req_header: &RequestHeader,
+    peer_ip: Option<IpAddr>,
+) -> Result<ClientIp, ClientIpRejection> {
+    Ok(ClientIp(config.extract(&req_header.headers, peer_ip)?))
+}
+
+/// [Sanitizes](sanitize) IP headers of an upstream request
+///
+/// [`SanitizePolicy::Append`] keeps the incoming `X-Forwarded-For` and
+/// `Forwarded` chains and appends the downstream peer, so hop-counting
+/// extractors upstream see the proxy hop. [`SanitizePolicy::ReplaceWithPeer`]
+/// replaces them with the resolved client IP instead. Other IP headers are
+/// removed either way.
+pub fn set_forwarded_headers(
+    upstream_request: &mut RequestHeader,
+    peer_addr: SocketAddr,
+    client_ip: IpAddr,
+    policy: SanitizePolicy,
+) -> pingora_error::Result<()> {
+    let written_addr = match policy {
+        SanitizePolicy::ReplaceWithPeer => SocketAddr::new(client_ip, 0),
+        SanitizePolicy::Strip | SanitizePolicy::Append => peer_addr,
+    };
+    let mut headers = upstream_request.headers.clone();
+    sanitize(&mut headers, policy, written_addr);
+    for header_name in &IP_HEADER_NAMES {
+        upstream_request.remove_header(header_name);
+        for value in headers.get_all(header_name) {
+            upstream_request.append_header(header_name, value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientIpSource;
+
+    fn request(cf_connecting_ip: Option<&str>) -> RequestHeader {
+        let mut request = RequestHeader::build("GET", b"/", None).unwrap();
+        if let Some(ip) = cf_connecting_ip {
+            request.insert_header("cf-connecting-ip", ip).unwrap();
+        }
+        request
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let config = ClientIpConfig::new(ClientIpSource::CfConnectingIp);
+        assert_eq!(
+            client_ip(&config, &request(Some("1.2.3.4")), None).unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap())
+        );
+        assert!(matches!(
+            client_ip(&config, &request(None), None).unwrap_err(),
+            ClientIpRejection::Extraction(_)
+        ));
+
+        let config = config.trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+        assert_eq!(
+            client_ip(&config, &request(Some("1.2.3.4")), None).unwrap_err(),
+            ClientIpRejection::MissingPeerAddr
+        );
+        assert_eq!(
+            client_ip(
+                &config,
+                &request(Some("1.2.3.4")),
+                Some("10.0.0.1".parse().unwrap())
+            )
+            .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+        assert_eq!(
+            client_ip(
+                &config,
+                &request(Some("1.2.3.4")),
+                Some("5.6.7.8".parse().unwrap())
+            )
+            .unwrap(),
+            ClientIp("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_client_ip() {
+        let stream = tokio_test::io::Builder::new()
+            .read(b"GET / HTTP/1.1\r\ncf-connecting-ip: 1.2.3.4\r\n\r\n")
+            .build();
+        let mut session = ServerSession::new_http1(Box::new(stream));
+        assert!(session.read_request().await.unwrap());
+
+        let config = ClientIpConfig::new(ClientIpSource::CfConnectingIp);
+        assert_eq!(
+            session_client_ip(&config, &session).unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap())
+        );
+        let config = config.trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+        assert_eq!(
+            session_client_ip(&config, &session).unwrap_err(),
+            ClientIpRejection::MissingPeerAddr,
+            "mock stream without a peer address"
+        );
+    }
+
+    #[test]
+    fn test_set_forwarded_headers() {
+        // The client 1.2.3.4 reaches the proxy through a Cloudflare edge
+        let downstream = || {
+            let mut request = request(Some("1.2.3.4"));
+            request.append_header("x-forwarded-for", "1.2.3.4").unwrap();
+            request
+                .append_header("forwarded", "for=1.2.3.4;proto=https")
+                .unwrap();
+            request
+        };
+        let peer_addr: SocketAddr = "173.245.48.1:443".parse().unwrap();
+        let config = ClientIpConfig::new(ClientIpSource::CfConnectingIp)
+            .trusted_proxies(["173.245.48.0/20".parse().unwrap()].into_iter().collect());
+        let ClientIp(client_ip) = client_ip(&config, &downstream(), Some(peer_addr.ip())).unwrap();
+        let values = |request: &RequestHeader, name| {
+            request
+                .headers
+                .get_all(name)
+                .iter()
+                .map(|v| v.to_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let mut request = downstream();
+        set_forwarded_headers(&mut request, peer_addr, client_ip, SanitizePolicy::Append).unwrap();
+        assert_eq!(
+            values(&request, "x-forwarded-for"),
+            ["1.2.3.4, 173.245.48.1"]
+        );
+        assert_eq!(
+            values(&request, "forwarded"),
+            ["for=1.2.3.4;proto=https, for=173.245.48.1"]
+        );
+        assert!(values(&request, "cf-connecting-ip").is_empty());
+        assert_eq!(
+            ClientIpSource::Traefik { trusted_proxies: 1 }.extract(&request.headers),
+            Ok(client_ip),
+            "upstream skipping the edge hop"
+        );
+
+        let mut request = downstream();
+        set_forwarded_headers(
+            &mut request,
+            peer_addr,
+            client_ip,
+            SanitizePolicy::ReplaceWithPeer,
+        )
+        .unwrap();
+        assert_eq!(values(&request, "x-forwarded-for"), ["1.2.3.4"]);
+        assert_eq!(values(&request, "forwarded"), ["for=1.2.3.4"]);
+        assert_eq!(values(&request, "x-real-ip"), ["1.2.3.4"]);
+
+        set_forwarded_headers(
+            &mut request,
+            peer_addr,
+            "2001:db8::1".parse().unwrap(),
+            SanitizePolicy::ReplaceWithPeer,
+        )
+        .unwrap();
+        assert_eq!(request.headers["x-forwarded-for"], "2001:db8::1");
+        assert_eq!(request.headers["forwarded"], "for=\"[2001:db8::1]\"");
+
+        let mut request = downstream();
+        set_forwarded_headers(&mut request, peer_addr, client_ip, SanitizePolicy::Strip).unwrap();
+        assert!(values(&request, "x-forwarded-for").is_empty());
+        assert!(values(&request, "forwarded").is_empty());
+    }
+}
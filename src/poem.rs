@@ -0,0 +1,126 @@
+//! [`poem`](https://docs.rs/poem) extractor
+//!
+//! [`ClientIp`] is configured by [`ClientIpConfig`] endpoint data, the
+//! [remote address](Request::remote_addr) is checked against trusted proxies:
+//!
+//! ```
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource};
+//! use poem::{EndpointExt, Route, get, handler};
+//!
+//! #[handler]
+//! fn index(ClientIp(ip): ClientIp) -> String {
+//!     ip.to_string()
+//! }
+//!
+//! let app = Route::new().at("/", get(index)).data(
+//!     ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+//!         .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+//! );
+//! ```
+
+use poem::{FromRequest, Request, RequestBody, error::ResponseError, http::StatusCode};
+
+use crate::{ClientIp, ClientIpConfig, ClientIpRejection};
+
+impl<'a> FromRequest<'a> for ClientIp {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Ok(req
+            .data::<ClientIpConfig>()
+            .ok_or(ClientIpRejection::MissingSource)
+            .and_then(|config| {
+                let peer_ip = req.remote_addr().as_socket_addr().map(|addr| addr.ip());
+                Ok(ClientIp(config.extract(req.headers(), peer_ip)?))
+            })?)
+    }
+}
+
+impl ResponseError for ClientIpRejection {
+    fn status(&self) -> StatusCode {
+        ClientIpRejection::status(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        Addr, Body, RequestParts,
+        http::{HeaderName, uri::Scheme},
+        web::{LocalAddr, RemoteAddr},
+    };
+
+    use super::*;
+    use crate::{ClientIpSource, Error};
+
+    fn request(config: Option<&ClientIpConfig>, x_real_ip: &str, peer: Option<&str>) -> Request {
+        let (parts, ()) = http::Request::builder()
+            .header("x-real-ip", x_real_ip)
+            .body(())
+            .unwrap()
+            .into_parts();
+        let remote_addr = peer.map_or_else(RemoteAddr::default, |peer| {
+            RemoteAddr(Addr::SocketAddr(peer.parse().unwrap()))
+        });
+        let mut req = Request::from_parts(
+            RequestParts::from((parts, LocalAddr::default(), remote_addr, Scheme::HTTP)),
+            Body::empty(),
+        );
+        if let Some(config) = config {
+            req.set_data(config.clone());
+        }
+        req
+    }
+
+    async fn extract(req: Request) -> poem::Result<ClientIp> {
+        ClientIp::from_request_without_body(&req).await
+    }
+
+    #[tokio::test]
+    async fn test_client_ip() {
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        let trusted = config
+            .clone()
+            .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+
+        assert_eq!(
+            extract(request(Some(&config), "1.2.3.4", None))
+                .await
+                .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(
+            extract(request(Some(&trusted), "1.2.3.4", Some("10.0.0.1:1234")))
+                .await
+                .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+        assert_eq!(
+            extract(request(Some(&trusted), "1.2.3.4", Some("5.6.7.8:1234")))
+                .await
+                .unwrap(),
+            ClientIp("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+
+        let err = extract(request(None, "1.2.3.4", None)).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            err.downcast_ref::<ClientIpRejection>(),
+            Some(&ClientIpRejection::MissingSource)
+        );
+
+        let err = extract(request(Some(&config), "foo", None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.downcast_ref::<ClientIpRejection>(),
+            Some(&ClientIpRejection::Extraction(
+                Error::MalformedHeaderValue {
+                    header_name: HeaderName::from_static("x-real-ip"),
+                    header_value: "foo".into()
+                }
+            ))
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Raw HTTP/1.x request heads
+//!
+//! [`RawHeaders`] scans header fields of a raw request buffer without
+//! building an [`http::HeaderMap`], so extractors run right on the bytes
+//! read from a socket:
+//!
+//! ```
+//! use client_ip::{raw::RawHeaders, rightmost_x_forwarded_for};
+//!
+//! let buf = b"GET / HTTP/1.1\r\n\
+//!     Host: example.com\r\n\
+//!     X-Forwarded-For: 1.1.1.1\r\n\
+//!     x-forwarded-for: 2.2.2.2\r\n\
+//!     \r\n\
+//!     body";
+//! let headers = RawHeaders::parse(buf).unwrap();
+//! assert_eq!(headers.head_len(), buf.len() - 4);
+//! assert_eq!(
+//!     rightmost_x_forwarded_for(&headers),
+//!     Ok("2.2.2.2".parse().unwrap())
+//! );
+//! ```
+
+use std::{borrow::Cow, fmt};
+
+use http::HeaderName;
+
+use crate::HeaderAccess;
+
+/// Header fields of a raw HTTP/1.x request head
+///
+/// Field names are matched case-insensitively, repeated fields are kept in
+/// order of appearance, and obsolete line folding (`obs-fold`) is replaced
+/// with a single space.
+#[derive(Debug, Clone)]
+pub struct RawHeaders<'a> {
+    fields: Vec<(&'a [u8], Cow<'a, [u8]>)>,
+    head_len: usize,
+}
+
+/// Error of parsing a raw request head
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestHeadError {
+    /// The buffer ends before the empty line terminating the head
+    Incomplete,
+    /// A field line is malformed, e.g. lacks a colon or the field name
+    /// contains invalid characters
+    InvalidFieldLine,
+}
+
+impl<'a> RawHeaders<'a> {
+    /// Parses the head of a request buffer starting with the request line
+    ///
+    /// Bytes after the head, e.g. the request body, are ignored.
+    pub fn parse(buf: &'a [u8]) -> Result<Self, RequestHeadError> {
+        let mut lines = Lines { buf, pos: 0 };
+
+        // Empty lines before the request line should be ignored
+        // https://www.rfc-editor.org/rfc/rfc9112#section-2.2
+        while lines.next_line()?.is_empty() {}
+
+        let mut fields: Vec<(&[u8], Cow<'_, [u8]>)> = Vec::new();
+        loop {
+            let line = lines.next_line()?;
+            if line.is_empty() {
+                return Ok(Self {
+                    fields,
+                    head_len: lines.pos,
+                });
+            }
+
+            if matches!(line[0], b' ' | b'\t') {
+                let (_, value) = fields
+                    .last_mut()
+                    .ok_or(RequestHeadError::InvalidFieldLine)?;
+                let continuation = line.trim_ascii();
+                if !continuation.is_empty() {
+                    let value = value.to_mut();
+                    if !value.is_empty() {
+                        value.push(b' ');
+                    }
+                    value.extend_from_slice(continuation);
+                }
+                continue;
+            }
+
+            let colon = line
+                .iter()
+                .position(|&b| b == b':')
+                .ok_or(RequestHeadError::InvalidFieldLine)?;
+            let name = &line[..colon];
+            if name.is_empty() || !name.iter().copied().all(is_tchar) {
+                return Err(RequestHeadError::InvalidFieldLine);
+            }
+            fields.push((name, Cow::Borrowed(line[colon + 1..].trim_ascii())));
+        }
+    }
+
+    /// Length of the head including the terminating empty line, i.e. offset
+    /// of the request body in the buffer
+    pub fn head_len(&self) -> usize {
+        self.head_len
+    }
+}
+
+impl HeaderAccess for RawHeaders<'_> {
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+        let header_name = header_name.as_str().as_bytes();
+        self.fields
+            .iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(header_name))
+            .map(|(_, value)| value.as_ref())
+    }
+}
+
+impl fmt::Display for RequestHeadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Incomplete => write!(f, "Request head is incomplete"),
+            Self::InvalidFieldLine => write!(f, "Request head contains an invalid field line"),
+        }
+    }
+}
+
+impl std::error::Error for RequestHeadError {}
+
+struct Lines<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lines<'a> {
+    /// Returns the next line without the line terminator, accepting a bare
+    /// `LF` as well as `CRLF`
+    fn next_line(&mut self) -> Result<&'a [u8], RequestHeadError> {
+        let rest = &self.buf[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or(RequestHeadError::Incomplete)?;
+        self.pos += len + 1;
+        let line = &rest[..len];
+        Ok(line.strip_suffix(b"\r").unwrap_or(line))
+    }
+}
+
+/// Field name characters
+/// https://www.rfc-editor.org/rfc/rfc9110#section-5.6.2
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, rightmost_x_forwarded_for, x_real_ip};
+
+    #[test]
+    fn test_parse() {
+        let headers = RawHeaders::parse(
+            b"\r\nGET / HTTP/1.1\n\
+            X-Forwarded-For: 1.1.1.1,\r\n  \t 2.2.2.2 \r\n\
+            X-REAL-IP:3.3.3.3\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rightmost_x_forwarded_for(&headers),
+            Ok("2.2.2.2".parse().unwrap()),
+            "obs-fold"
+        );
+        assert_eq!(
+            x_real_ip(&headers),
+            Ok("3.3.3.3".parse().unwrap()),
+            "case-insensitive names"
+        );
+
+        let headers = RawHeaders::parse(
+            b"GET / HTTP/1.1\r\nX-Real-Ip: 1.1.1.1\r\nX-Real-Ip: 2.2.2.2\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            x_real_ip(&headers),
+            Err(Error::SingleHeaderRequired {
+                header_name: HeaderName::from_static("x-real-ip")
+            }),
+            "repeated fields"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (buf, err) in [
+            (&b""[..], RequestHeadError::Incomplete),
+            (b"GET / HTTP/1.1\r\n", RequestHeadError::Incomplete),
+            (
+                b"GET / HTTP/1.1\r\nX-Real-Ip: 1.1.1.1\r\n",
+                RequestHeadError::Incomplete,
+            ),
+            (
+                b"GET / HTTP/1.1\r\n 1.1.1.1\r\n\r\n",
+                RequestHeadError::InvalidFieldLine,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nX-Real-Ip 1.1.1.1\r\n\r\n",
+                RequestHeadError::InvalidFieldLine,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nX-Real-Ip : 1.1.1.1\r\n\r\n",
+                RequestHeadError::InvalidFieldLine,
+            ),
+        ] {
+            assert_eq!(
+                RawHeaders::parse(buf).unwrap_err(),
+                err,
+                "{:?}",
+                String::from_utf8_lossy(buf)
+            );
+        }
+    }
+}
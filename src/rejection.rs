@@ -0,0 +1,48 @@
+use std::fmt;
+
+use http::StatusCode;
+
+use crate::Error;
+
+/// Rejection of framework client IP extractors
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientIpRejection {
+    /// The extractor isn't configured, e.g. a [`ClientIpConfig`] isn't
+    /// provided
+    ///
+    /// [`ClientIpConfig`]: crate::ClientIpConfig
+    MissingSource,
+    /// The peer address required to check trusted proxies isn't available
+    MissingPeerAddr,
+    /// The client IP can't be extracted
+    Extraction(Error),
+}
+
+impl ClientIpRejection {
+    /// Response status: `500 Internal Server Error` for server
+    /// misconfiguration and `400 Bad Request` for extraction errors
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::MissingSource | Self::MissingPeerAddr => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Extraction(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl From<Error> for ClientIpRejection {
+    fn from(e: Error) -> Self {
+        Self::Extraction(e)
+    }
+}
+
+impl fmt::Display for ClientIpRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSource => write!(f, "Client IP source isn't configured"),
+            Self::MissingPeerAddr => write!(f, "Peer address isn't available"),
+            Self::Extraction(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ClientIpRejection {}
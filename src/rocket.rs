@@ -0,0 +1,125 @@
+//! [`rocket`](https://docs.rs/rocket) request guard
+//!
+//! [`ClientIp`] is configured by [`ClientIpConfig`] managed state, the
+//! [remote address](Request::remote) is checked against trusted proxies:
+//!
+//! ```
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource};
+//! use rocket::get;
+//!
+//! #[get("/")]
+//! fn index(client_ip: ClientIp) -> String {
+//!     client_ip.0.to_string()
+//! }
+//!
+//! let rocket = rocket::build()
+//!     .manage(
+//!         ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+//!             .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+//!     )
+//!     .mount("/", rocket::routes![index]);
+//! ```
+
+use http::HeaderName;
+use rocket::{
+    Request,
+    http::{HeaderMap, Status},
+    request::{FromRequest, Outcome},
+};
+
+use crate::{ClientIp, ClientIpConfig, ClientIpRejection, HeaderAccess};
+
+impl HeaderAccess for HeaderMap<'_> {
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+        self.get(header_name.as_str()).map(str::as_bytes)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = ClientIpRejection;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = req
+            .rocket()
+            .state::<ClientIpConfig>()
+            .ok_or(ClientIpRejection::MissingSource)
+            .and_then(|config| {
+                let peer_ip = req.remote().map(|addr| addr.ip());
+                Ok(ClientIp(config.extract(req.headers(), peer_ip)?))
+            });
+        match result {
+            Ok(client_ip) => Outcome::Success(client_ip),
+            Err(rejection) => Outcome::Error((Status::new(rejection.status().as_u16()), rejection)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{Build, Rocket, get, http::Header, local::blocking::Client, routes};
+
+    use super::*;
+    use crate::ClientIpSource;
+
+    #[get("/")]
+    fn index(client_ip: ClientIp) -> String {
+        client_ip.0.to_string()
+    }
+
+    fn rocket_client(config: Option<ClientIpConfig>) -> Client {
+        let rocket: Rocket<Build> = rocket::build().mount("/", routes![index]);
+        Client::untracked(match config {
+            Some(config) => rocket.manage(config),
+            None => rocket,
+        })
+        .unwrap()
+    }
+
+    fn get(client: &Client, x_real_ip: &str, remote: Option<&str>) -> (Status, Option<String>) {
+        let mut req = client
+            .get("/")
+            .header(Header::new("x-real-ip", x_real_ip.to_owned()));
+        if let Some(remote) = remote {
+            req = req.remote(remote.parse().unwrap());
+        }
+        let res = req.dispatch();
+        let status = res.status();
+        (
+            status,
+            (status == Status::Ok).then(|| res.into_string().unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        let trusted = config
+            .clone()
+            .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+
+        assert_eq!(
+            get(&rocket_client(None), "1.2.3.4", None),
+            (Status::InternalServerError, None)
+        );
+
+        let client = rocket_client(Some(config));
+        assert_eq!(
+            get(&client, "1.2.3.4", None),
+            (Status::Ok, Some("1.2.3.4".into()))
+        );
+        assert_eq!(get(&client, "foo", None), (Status::BadRequest, None));
+
+        let client = rocket_client(Some(trusted));
+        assert_eq!(
+            get(&client, "1.2.3.4", Some("10.0.0.1:1234")),
+            (Status::Ok, Some("1.2.3.4".into())),
+            "trusted proxy"
+        );
+        assert_eq!(
+            get(&client, "1.2.3.4", Some("5.6.7.8:1234")),
+            (Status::Ok, Some("5.6.7.8".into())),
+            "untrusted peer"
+        );
+    }
+}
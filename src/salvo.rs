@@ -0,0 +1,159 @@
+//! [`salvo`](https://docs.rs/salvo) extractor
+//!
+//! [`ClientIp`] is configured by [`ClientIpConfig`] inserted into the depot,
+//! the configuration is itself a handler doing that. The
+//! [remote address](Request::remote_addr) is checked against trusted proxies:
+//!
+//! ```
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource};
+//! use salvo_core::prelude::*;
+//!
+//! #[handler]
+//! async fn index(client_ip: ClientIp) -> String {
+//!     client_ip.0.to_string()
+//! }
+//!
+//! let router = Router::new()
+//!     .hoop(
+//!         ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+//!             .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+//!     )
+//!     .get(index);
+//! ```
+
+use std::fmt::Debug;
+
+use salvo_core::{
+    Depot, FlowCtrl, Handler, Request, Response, Writer, async_trait,
+    extract::{Extractible, Metadata},
+    writing::Scribe,
+};
+
+use crate::{ClientIp, ClientIpConfig, ClientIpRejection};
+
+#[async_trait]
+impl Handler for ClientIpConfig {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        depot.insert_typed(self.clone());
+    }
+}
+
+impl<'ex> Extractible<'ex> for ClientIp {
+    fn metadata() -> &'static Metadata {
+        static METADATA: Metadata = Metadata::new("");
+        &METADATA
+    }
+
+    async fn extract(
+        req: &'ex mut Request,
+        depot: &'ex mut Depot,
+    ) -> Result<Self, impl Writer + Send + Debug + 'static> {
+        depot
+            .get_typed::<ClientIpConfig>()
+            .map_err(|_| ClientIpRejection::MissingSource)
+            .and_then(|config| {
+                let peer_ip = req.remote_addr().ip();
+                Ok(ClientIp(config.extract(req.headers(), peer_ip)?))
+            })
+    }
+}
+
+impl Scribe for ClientIpRejection {
+    fn render(self, res: &mut Response) {
+        res.render_with_status(self.status(), self.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+    use salvo_core::conn::SocketAddr;
+
+    use super::*;
+    use crate::ClientIpSource;
+
+    fn request(x_real_ip: &str, peer: Option<&str>) -> Request {
+        let mut req = Request::new();
+        req.headers_mut()
+            .insert("x-real-ip", x_real_ip.parse().unwrap());
+        if let Some(peer) = peer {
+            *req.remote_addr_mut() =
+                SocketAddr::from(peer.parse::<std::net::SocketAddr>().unwrap());
+        }
+        req
+    }
+
+    async fn extract(
+        config: Option<&ClientIpConfig>,
+        mut req: Request,
+    ) -> Result<ClientIp, Response> {
+        let mut depot = Depot::new();
+        if let Some(config) = config {
+            config
+                .handle(
+                    &mut req,
+                    &mut depot,
+                    &mut Response::new(),
+                    &mut FlowCtrl::new(vec![]),
+                )
+                .await;
+        }
+        match ClientIp::extract(&mut req, &mut depot).await {
+            Ok(ip) => Ok(ip),
+            Err(e) => {
+                let mut res = Response::new();
+                e.write(&mut req, &mut depot, &mut res).await;
+                Err(res)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_ip() {
+        let config = ClientIpConfig::new(ClientIpSource::XRealIp);
+        let trusted = config
+            .clone()
+            .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect());
+
+        assert_eq!(
+            extract(None, request("1.2.3.4", None))
+                .await
+                .unwrap_err()
+                .status_code,
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(
+            extract(Some(&config), request("foo", None))
+                .await
+                .unwrap_err()
+                .status_code,
+            Some(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            extract(Some(&config), request("1.2.3.4", None))
+                .await
+                .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(
+            extract(Some(&trusted), request("1.2.3.4", Some("10.0.0.1:1234")))
+                .await
+                .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+        assert_eq!(
+            extract(Some(&trusted), request("1.2.3.4", Some("5.6.7.8:1234")))
+                .await
+                .unwrap(),
+            ClientIp("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Sanitizing client-supplied IP headers at the edge
+//!
+//! An edge service must not forward IP headers sent by clients, otherwise
+//! backends trusting them are easily fooled. [`sanitize`] removes or rewrites
+//! every header the extractors of this crate read:
+//!
+//! ```
+//! use client_ip::sanitize::{SanitizePolicy, sanitize};
+//! use http::HeaderMap;
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("x-real-ip", "6.6.6.6".parse().unwrap());
+//! headers.insert("x-forwarded-for", "6.6.6.6".parse().unwrap());
+//!
+//! let present = sanitize(
+//!     &mut headers,
+//!     SanitizePolicy::Append,
+//!     "1.2.3.4:1234".parse().unwrap(),
+//! );
+//! assert_eq!(present, ["x-forwarded-for", "x-real-ip"]);
+//! assert_eq!(headers.get("x-real-ip"), None);
+//! assert_eq!(headers["x-forwarded-for"], "6.6.6.6, 1.2.3.4");
+//! ```
+
+use std::net::SocketAddr;
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{forwarding::ForwardingWriter, header_names::*};
+
+/// Names of all the headers the extractors read
+pub static IP_HEADER_NAMES: [HeaderName; 10] = [
+    CF_CONNECTING_IP,
+    CLOUDFRONT_VIEWER_ADDRESS,
+    FLY_CLIENT_IP,
+    FORWARDED,
+    TRUE_CLIENT_IP,
+    X_ENVOY_EXTERNAL_ADDRESS,
+    X_ENVOY_INTERNAL,
+    X_FORWARDED_FOR,
+    X_ORIGINAL_FORWARDED_FOR,
+    X_REAL_IP,
+];
+
+/// What [`sanitize`] does with IP headers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SanitizePolicy {
+    /// Removes all of them
+    #[default]
+    Strip,
+    /// Removes all of them and sets `X-Forwarded-For`, `Forwarded` and
+    /// `X-Real-Ip` to the peer IP
+    ReplaceWithPeer,
+    /// Appends the peer IP to `X-Forwarded-For` and `Forwarded` and removes
+    /// the rest
+    Append,
+}
+
+/// Sanitizes [IP headers](IP_HEADER_NAMES) of a request according to the
+/// policy, returns names of the headers the request contained
+pub fn sanitize(
+    header_map: &mut HeaderMap,
+    policy: SanitizePolicy,
+    peer_addr: SocketAddr,
+) -> Vec<HeaderName> {
+    let present: Vec<HeaderName> = IP_HEADER_NAMES
+        .iter()
+        .filter(|header_name| header_map.contains_key(*header_name))
+        .cloned()
+        .collect();
+
+    for header_name in &present {
+        if policy != SanitizePolicy::Append || !is_appendable(header_name) {
+            header_map.remove(header_name);
+        }
+    }
+
+    match policy {
+        SanitizePolicy::Strip => (),
+        SanitizePolicy::ReplaceWithPeer | SanitizePolicy::Append => {
+            ForwardingWriter::new()
+                .write(header_map, peer_addr)
+                .expect("existing values and the peer IP are valid header values");
+        }
+    }
+    if policy == SanitizePolicy::ReplaceWithPeer {
+        let peer_ip = HeaderValue::try_from(peer_addr.ip().to_string())
+            .expect("IP addresses are valid header values");
+        header_map.insert(X_REAL_IP, peer_ip);
+    }
+
+    present
+}
+
+fn is_appendable(header_name: &HeaderName) -> bool {
+    *header_name == X_FORWARDED_FOR || *header_name == FORWARDED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientIpSource;
+
+    fn spoofed_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for header_name in &IP_HEADER_NAMES {
+            headers.insert(header_name, "6.6.6.6".parse().unwrap());
+        }
+        headers.insert(X_FORWARDED_FOR, "6.6.6.6".parse().unwrap());
+        headers.insert(FORWARDED, "for=6.6.6.6".parse().unwrap());
+        headers.insert("user-agent", "test".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let peer_addr = "1.2.3.4:1234".parse().unwrap();
+
+        let mut headers = spoofed_headers();
+        assert_eq!(
+            sanitize(&mut headers, SanitizePolicy::Strip, peer_addr),
+            IP_HEADER_NAMES
+        );
+        assert_eq!(headers.keys().collect::<Vec<_>>(), ["user-agent"]);
+
+        let mut headers = spoofed_headers();
+        sanitize(&mut headers, SanitizePolicy::ReplaceWithPeer, peer_addr);
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers[X_FORWARDED_FOR], "1.2.3.4");
+        assert_eq!(headers[FORWARDED], "for=1.2.3.4");
+        assert_eq!(headers[X_REAL_IP], "1.2.3.4");
+
+        let mut headers = spoofed_headers();
+        sanitize(&mut headers, SanitizePolicy::Append, peer_addr);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[X_FORWARDED_FOR], "6.6.6.6, 1.2.3.4");
+        assert_eq!(headers[FORWARDED], "for=6.6.6.6, for=1.2.3.4");
+
+        let mut headers = HeaderMap::new();
+        assert!(sanitize(&mut headers, SanitizePolicy::Strip, peer_addr).is_empty());
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_ip_header_names_cover_sources() {
+        for source in [
+            ClientIpSource::CfConnectingIp,
+            ClientIpSource::CloudFrontViewerAddress,
+            ClientIpSource::Envoy {
+                xff_num_trusted_hops: 0,
+            },
+            ClientIpSource::FlyClientIp,
+            ClientIpSource::IngressNginx { trusted_proxies: 0 },
+            #[cfg(feature = "forwarded-header")]
+            ClientIpSource::RightmostForwarded,
+            ClientIpSource::RightmostXForwardedFor,
+            ClientIpSource::Traefik { trusted_proxies: 0 },
+            ClientIpSource::TrueClientIp,
+            ClientIpSource::XEnvoyExternalAddress,
+            ClientIpSource::XRealIp,
+        ] {
+            for header_name in source.header_names() {
+                assert!(
+                    IP_HEADER_NAMES.contains(&header_name),
+                    "{source:?}: {header_name}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Shadow-mode comparison of extraction strategies
+//!
+//! Switching to another strategy in production is risky. [`ShadowComparator`]
+//! runs a candidate [`ClientIpConfig`] next to the primary one, returns the
+//! primary's result and reports disagreements to a callback:
+//!
+//! ```
+//! use std::sync::Mutex;
+//!
+//! use client_ip::{
+//!     ClientIpConfig, ClientIpSource,
+//!     shadow::ShadowComparator,
+//! };
+//! use http::HeaderMap;
+//!
+//! let disagreements = Mutex::new(Vec::new());
+//! let comparator = ShadowComparator::new(
+//!     ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor),
+//!     ClientIpConfig::new(ClientIpSource::Traefik { trusted_proxies: 1 }),
+//!     |disagreement| disagreements.lock().unwrap().push(disagreement.clone()),
+//! );
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+//!
+//! assert_eq!(
+//!     comparator.extract(&headers, None),
+//!     Ok("2.2.2.2".parse().unwrap())
+//! );
+//! let disagreements = disagreements.into_inner().unwrap();
+//! assert_eq!(disagreements[0].candidate, Ok("1.1.1.1".parse().unwrap()));
+//! ```
+
+use std::net::IpAddr;
+
+use crate::{ClientIpConfig, ClientIpRejection, HeaderAccess, explain::Explanation};
+
+/// Runs a candidate strategy in the shadow of the primary one
+pub struct ShadowComparator<F> {
+    primary: ClientIpConfig,
+    candidate: ClientIpConfig,
+    on_disagreement: F,
+}
+
+/// Results of the strategies differing for a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disagreement {
+    /// Result of the primary strategy
+    pub primary: Result<IpAddr, ClientIpRejection>,
+    /// Result of the candidate strategy
+    pub candidate: Result<IpAddr, ClientIpRejection>,
+    /// Trace of the primary strategy, absent if it lacks the peer IP
+    pub primary_trace: Option<Explanation>,
+    /// Trace of the candidate strategy, absent if it lacks the peer IP
+    pub candidate_trace: Option<Explanation>,
+}
+
+impl<F: Fn(&Disagreement)> ShadowComparator<F> {
+    /// Creates a comparator calling `on_disagreement` for requests the
+    /// strategies disagree on
+    pub fn new(primary: ClientIpConfig, candidate: ClientIpConfig, on_disagreement: F) -> Self {
+        Self {
+            primary,
+            candidate,
+            on_disagreement,
+        }
+    }
+
+    /// Extracts the client IP with both strategies, returns the result of the
+    /// primary one
+    ///
+    /// Traces are only collected for disagreements.
+    pub fn extract(
+        &self,
+        header_map: &impl HeaderAccess,
+        peer_ip: Option<IpAddr>,
+    ) -> Result<IpAddr, ClientIpRejection> {
+        let primary = self.primary.extract(header_map, peer_ip);
+        let candidate = self.candidate.extract(header_map, peer_ip);
+        if primary != candidate {
+            (self.on_disagreement)(&Disagreement {
+                primary: primary.clone(),
+                candidate,
+                primary_trace: self.primary.explain(header_map, peer_ip),
+                candidate_trace: self.candidate.explain(header_map, peer_ip),
+            });
+        }
+        primary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use http::HeaderMap;
+
+    use super::*;
+    use crate::{
+        ClientIpSource,
+        explain::{Extractor, PeerDecision},
+    };
+
+    #[test]
+    fn test_extract() {
+        let disagreements = RefCell::new(Vec::new());
+        let comparator = ShadowComparator::new(
+            ClientIpConfig::new(ClientIpSource::XRealIp),
+            ClientIpConfig::new(ClientIpSource::RightmostXForwardedFor)
+                .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+            |disagreement: &Disagreement| disagreements.borrow_mut().push(disagreement.clone()),
+        );
+        let proxy_ip = Some("10.0.0.1".parse().unwrap());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "1.1.1.1".parse().unwrap());
+        headers.insert("x-forwarded-for", "1.1.1.1".parse().unwrap());
+        assert_eq!(
+            comparator.extract(&headers, proxy_ip),
+            Ok("1.1.1.1".parse().unwrap())
+        );
+        assert!(disagreements.borrow().is_empty());
+
+        assert_eq!(
+            comparator.extract(&headers, Some("2.2.2.2".parse().unwrap())),
+            Ok("1.1.1.1".parse().unwrap())
+        );
+        let disagreement = disagreements.borrow_mut().pop().unwrap();
+        assert_eq!(disagreement.candidate, Ok("2.2.2.2".parse().unwrap()));
+        assert!(disagreement.primary_trace.is_some());
+        assert_eq!(
+            disagreement.candidate_trace.unwrap().peer,
+            Some(PeerDecision {
+                ip: "2.2.2.2".parse().unwrap(),
+                trusted: false
+            })
+        );
+
+        assert_eq!(
+            comparator.extract(&headers, None),
+            Ok("1.1.1.1".parse().unwrap())
+        );
+        let disagreement = disagreements.borrow_mut().pop().unwrap();
+        assert_eq!(
+            disagreement.candidate,
+            Err(ClientIpRejection::MissingPeerAddr)
+        );
+        assert_eq!(disagreement.candidate_trace, None);
+
+        headers.remove("x-real-ip");
+        headers.insert("x-forwarded-for", "3.3.3.3".parse().unwrap());
+        assert!(comparator.extract(&headers, proxy_ip).is_err());
+        let disagreement = disagreements.borrow_mut().pop().unwrap();
+        assert_eq!(disagreement.candidate, Ok("3.3.3.3".parse().unwrap()));
+        assert_eq!(
+            disagreement.candidate_trace.unwrap().extractor,
+            Extractor::Source(ClientIpSource::RightmostXForwardedFor)
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use http::HeaderName;
+
+use crate::{
+    HeaderAccess, Result,
+    explain::{Explanation, Extractor, Recorder, Tracer},
+    header_names::*,
+};
+
+/// A configurable source of the client IP for framework integrations
+///
+/// Each variant corresponds to the extractor of the same name. Sources are
+/// parsed from and displayed as kebab-case names, with the parameter of
+/// parameterized ones after a colon, e.g. `x-real-ip` or `traefik:2`. The
+/// parameter defaults to zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClientIpSource {
+    /// [`cf_connecting_ip`](crate::cf_connecting_ip)
+    CfConnectingIp,
+    /// [`cloudfront_viewer_address`](crate::cloudfront_viewer_address)
+    CloudFrontViewerAddress,
+    /// [`envoy`](crate::envoy)
+    Envoy {
+        /// Envoy's `xff_num_trusted_hops` setting
+        xff_num_trusted_hops: usize,
+    },
+    /// [`fly_client_ip`](crate::fly_client_ip)
+    FlyClientIp,
+    /// [`ingress_nginx`](crate::ingress_nginx)
+    IngressNginx {
+        /// Number of proxies in front of the ingress
+        trusted_proxies: usize,
+    },
+    #[cfg(feature = "forwarded-header")]
+    /// [`rightmost_forwarded`](crate::rightmost_forwarded)
+    RightmostForwarded,
+    /// [`rightmost_x_forwarded_for`](crate::rightmost_x_forwarded_for)
+    RightmostXForwardedFor,
+    /// [`traefik`](crate::traefik)
+    Traefik {
+        /// Number of trusted proxies in front of Traefik
+        trusted_proxies: usize,
+    },
+    /// [`true_client_ip`](crate::true_client_ip)
+    TrueClientIp,
+    /// [`x_envoy_external_address`](crate::x_envoy_external_address)
+    XEnvoyExternalAddress,
+    /// [`x_real_ip`](crate::x_real_ip)
+    XRealIp,
+}
+
+impl ClientIpSource {
+    /// All the sources, parameterized ones with zero hops
+    pub const ALL: &[Self] = &[
+        Self::CfConnectingIp,
+        Self::CloudFrontViewerAddress,
+        Self::Envoy {
+            xff_num_trusted_hops: 0,
+        },
+        Self::FlyClientIp,
+        Self::IngressNginx { trusted_proxies: 0 },
+        #[cfg(feature = "forwarded-header")]
+        Self::RightmostForwarded,
+        Self::RightmostXForwardedFor,
+        Self::Traefik { trusted_proxies: 0 },
+        Self::TrueClientIp,
+        Self::XEnvoyExternalAddress,
+        Self::XRealIp,
+    ];
+
+    /// Returns the kebab-case name of the source without the parameter
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CfConnectingIp => "cf-connecting-ip",
+            Self::CloudFrontViewerAddress => "cloudfront-viewer-address",
+            Self::Envoy { .. } => "envoy",
+            Self::FlyClientIp => "fly-client-ip",
+            Self::IngressNginx { .. } => "ingress-nginx",
+            #[cfg(feature = "forwarded-header")]
+            Self::RightmostForwarded => "rightmost-forwarded",
+            Self::RightmostXForwardedFor => "rightmost-x-forwarded-for",
+            Self::Traefik { .. } => "traefik",
+            Self::TrueClientIp => "true-client-ip",
+            Self::XEnvoyExternalAddress => "x-envoy-external-address",
+            Self::XRealIp => "x-real-ip",
+        }
+    }
+
+    /// Returns the parameter of a parameterized source
+    fn parameter(&self) -> Option<usize> {
+        match *self {
+            Self::Envoy {
+                xff_num_trusted_hops,
+            } => Some(xff_num_trusted_hops),
+            Self::IngressNginx { trusted_proxies } | Self::Traefik { trusted_proxies } => {
+                Some(trusted_proxies)
+            }
+            _ => None,
+        }
+    }
+
+    /// Extracts the client IP using the corresponding extractor, converting
+    /// IPv4-mapped addresses to IPv4
+    pub fn extract(&self, header_map: &impl HeaderAccess) -> Result<IpAddr> {
+        self.extract_traced(header_map, &mut ())
+    }
+
+    /// Extracts the client IP the same way as [`extract`](Self::extract),
+    /// tracing the decision
+    pub fn explain(&self, header_map: &impl HeaderAccess) -> Explanation {
+        let mut recorder = Recorder::default();
+        let result = self.extract_traced(header_map, &mut recorder);
+        recorder.finish(Extractor::Source(*self), result)
+    }
+
+    pub(crate) fn extract_traced(
+        &self,
+        header_map: &impl HeaderAccess,
+        tracer: &mut impl Tracer,
+    ) -> Result<IpAddr> {
+        let ip = match *self {
+            Self::CfConnectingIp => {
+                crate::ip_from_single_header(header_map, &CF_CONNECTING_IP, tracer)
+            }
+            Self::CloudFrontViewerAddress => {
+                crate::cloudfront_viewer_address_traced(header_map, tracer)
+            }
+            Self::Envoy {
+                xff_num_trusted_hops,
+            } => crate::envoy_traced(header_map, xff_num_trusted_hops, tracer)
+                .map(|client| client.ip),
+            Self::FlyClientIp => crate::ip_from_single_header(header_map, &FLY_CLIENT_IP, tracer),
+            Self::IngressNginx { trusted_proxies } => {
+                crate::ingress_nginx_traced(header_map, trusted_proxies, tracer)
+            }
+            #[cfg(feature = "forwarded-header")]
+            Self::RightmostForwarded => crate::rightmost_forwarded_resolved_traced(
+                header_map,
+                &|_: &crate::forwarding::ForwardedNode| None,
+                tracer,
+            ),
+            Self::RightmostXForwardedFor => {
+                crate::rightmost_x_forwarded_for_traced(header_map, tracer)
+            }
+            Self::Traefik { trusted_proxies } => {
+                crate::nth_rightmost_ip(header_map, &X_FORWARDED_FOR, trusted_proxies, tracer)
+            }
+            Self::TrueClientIp => crate::ip_from_single_header(header_map, &TRUE_CLIENT_IP, tracer),
+            Self::XEnvoyExternalAddress => {
+                crate::ip_from_single_header(header_map, &X_ENVOY_EXTERNAL_ADDRESS, tracer)
+            }
+            Self::XRealIp => crate::ip_from_single_header(header_map, &X_REAL_IP, tracer),
+        }?;
+        Ok(ip.to_canonical())
+    }
+
+    /// Returns names of the headers the extractor reads
+    pub fn header_names(&self) -> Vec<HeaderName> {
+        match self {
+            Self::CfConnectingIp => vec![CF_CONNECTING_IP],
+            Self::CloudFrontViewerAddress => vec![CLOUDFRONT_VIEWER_ADDRESS],
+            Self::Envoy { .. } => vec![X_FORWARDED_FOR, X_ENVOY_INTERNAL],
+            Self::FlyClientIp => vec![FLY_CLIENT_IP],
+            Self::IngressNginx { .. } => vec![X_ORIGINAL_FORWARDED_FOR, X_FORWARDED_FOR],
+            #[cfg(feature = "forwarded-header")]
+            Self::RightmostForwarded => vec![FORWARDED],
+            Self::RightmostXForwardedFor | Self::Traefik { .. } => vec![X_FORWARDED_FOR],
+            Self::TrueClientIp => vec![TRUE_CLIENT_IP],
+            Self::XEnvoyExternalAddress => vec![X_ENVOY_EXTERNAL_ADDRESS],
+            Self::XRealIp => vec![X_REAL_IP],
+        }
+    }
+}
+
+impl FromStr for ClientIpSource {
+    type Err = InvalidClientIpSource;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || InvalidClientIpSource(s.to_owned());
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let source = *Self::ALL
+            .iter()
+            .find(|source| source.name() == name)
+            .ok_or_else(invalid)?;
+        Ok(match (source, parameter) {
+            (source, None) => source,
+            (Self::Envoy { .. }, Some(xff_num_trusted_hops)) => Self::Envoy {
+                xff_num_trusted_hops,
+            },
+            (Self::IngressNginx { .. }, Some(trusted_proxies)) => {
+                Self::IngressNginx { trusted_proxies }
+            }
+            (Self::Traefik { .. }, Some(trusted_proxies)) => Self::Traefik { trusted_proxies },
+            (_, Some(_)) => return Err(invalid()),
+        })
+    }
+}
+
+impl fmt::Display for ClientIpSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())?;
+        match self.parameter() {
+            Some(parameter) => write!(f, ":{parameter}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Serialized as the displayed name, e.g. `"traefik:1"`
+#[cfg(feature = "serde")]
+impl serde::Serialize for ClientIpSource {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Error of parsing a [`ClientIpSource`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidClientIpSource(String);
+
+impl fmt::Display for InvalidClientIpSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid client IP source: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidClientIpSource {}
+
+/// The client IP extracted by a [`ClientIpSource`], stored into request
+/// extensions by framework integrations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("x-real-ip".parse(), Ok(ClientIpSource::XRealIp));
+        assert_eq!(
+            "traefik:2".parse(),
+            Ok(ClientIpSource::Traefik { trusted_proxies: 2 })
+        );
+        assert_eq!(
+            "envoy".parse(),
+            Ok(ClientIpSource::Envoy {
+                xff_num_trusted_hops: 0
+            })
+        );
+        for invalid in ["traefik:x", "traefik:", "x-real-ip:1", "foo", "XRealIp"] {
+            assert_eq!(
+                invalid.parse::<ClientIpSource>(),
+                Err(InvalidClientIpSource(invalid.to_owned())),
+                "{invalid}"
+            );
+        }
+
+        for source in ClientIpSource::ALL {
+            assert_eq!(source.to_string().parse().as_ref(), Ok(source));
+        }
+        assert_eq!(
+            ClientIpSource::IngressNginx { trusted_proxies: 1 }.to_string(),
+            "ingress-nginx:1"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize() {
+        let source = ClientIpSource::Traefik { trusted_proxies: 1 };
+        let json = serde_json::to_value(source).unwrap();
+        assert_eq!(json, "traefik:1");
+        assert_eq!(json.as_str().unwrap().parse(), Ok(source), "round trip");
+    }
+}
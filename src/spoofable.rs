@@ -0,0 +1,127 @@
+//! Extractors returning client-controlled values
+//!
+//! **Never use these extractors for anything security-related** (rate
+//! limiting, allow lists, audit logs, etc.): any client can put an arbitrary
+//! value into the headers they read. They're meant for analytics or
+//! geolocation, where a best guess about the original client is preferred over
+//! the address of an intermediate proxy. Use the extractors from the crate root
+//! otherwise.
+
+use std::net::IpAddr;
+
+use http::HeaderName;
+
+use crate::{
+    Error, HeaderAccess, Result, classify,
+    explain::{Selection, Tracer, Verdict},
+    header_names, list_header_entries,
+};
+
+/// Extracts the leftmost globally routable IP from `X-Forwarded-For` header
+///
+/// Entries that aren't valid IPs as well as private, loopback, link-local,
+/// CGNAT, and other non-global addresses (see [`IpClass`](crate::IpClass)) are
+/// skipped, as those are usually inserted by corporate or carrier proxies in
+/// front of the client.
+///
+/// **The result is controlled by the client**, see the [module
+/// docs](self).
+pub fn leftmost_non_private_x_forwarded_for(header_map: &impl HeaderAccess) -> Result<IpAddr> {
+    leftmost_non_private_x_forwarded_for_traced(header_map, &mut ())
+}
+
+pub(crate) fn leftmost_non_private_x_forwarded_for_traced(
+    header_map: &impl HeaderAccess,
+    tracer: &mut impl Tracer,
+) -> Result<IpAddr> {
+    const HEADER_NAME: HeaderName = header_names::X_FORWARDED_FOR;
+
+    tracer.header(header_map, &HEADER_NAME, Selection::All);
+    let entries = list_header_entries(header_map, &HEADER_NAME)?;
+
+    tracer.hops_of(&HEADER_NAME);
+    let mut hops = entries.into_iter();
+    while let Some((_, entry)) = hops.next() {
+        match entry.parse::<IpAddr>() {
+            Err(_) => tracer.hop(entry, Verdict::SkippedMalformed),
+            Ok(ip) if !classify(ip).is_global() => tracer.hop(entry, Verdict::SkippedNonGlobal),
+            Ok(ip) => {
+                tracer.hop(entry, Verdict::Selected);
+                for (_, entry) in hops {
+                    tracer.hop(entry, Verdict::Ignored);
+                }
+                return Ok(ip);
+            }
+        }
+    }
+    Err(Error::NoGlobalIpInList {
+        header_name: HEADER_NAME,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+
+    fn headers<'a>(items: impl IntoIterator<Item = (&'a str, &'a str)>) -> HeaderMap {
+        HeaderMap::from_iter(
+            items
+                .into_iter()
+                .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap())),
+        )
+    }
+
+    #[test]
+    fn test_leftmost_non_private_x_forwarded_for() {
+        let header = "x-forwarded-for";
+
+        assert_eq!(
+            leftmost_non_private_x_forwarded_for(&headers([])).unwrap_err(),
+            Error::AbsentHeader {
+                header_name: HeaderName::from_static(header)
+            }
+        );
+        assert_eq!(
+            leftmost_non_private_x_forwarded_for(&headers([(header, "ы")])).unwrap_err(),
+            Error::NonAsciiHeaderValue {
+                header_name: HeaderName::from_static(header)
+            }
+        );
+        assert_eq!(
+            leftmost_non_private_x_forwarded_for(&headers([(
+                header,
+                "foo, 10.0.0.1, 172.16.0.1, 192.168.0.1, 127.0.0.1, 169.254.0.1, 100.64.0.1, \
+                 0.0.0.0, ::1, ::, fd00::1, fe80::1, ::ffff:10.0.0.1"
+            )]))
+            .unwrap_err(),
+            Error::NoGlobalIpInList {
+                header_name: HeaderName::from_static(header)
+            }
+        );
+
+        assert_eq!(
+            leftmost_non_private_x_forwarded_for(&headers([(
+                header,
+                "unknown, 10.0.0.1, 100.100.0.1, 1.2.3.4, 5.6.7.8"
+            )]))
+            .unwrap(),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            leftmost_non_private_x_forwarded_for(&headers([
+                (header, "192.168.1.1, fe80::1"),
+                (header, "2001:4860::1, 1.2.3.4")
+            ]))
+            .unwrap(),
+            "2001:4860::1".parse::<IpAddr>().unwrap(),
+            "multiple headers"
+        );
+        assert_eq!(
+            leftmost_non_private_x_forwarded_for(&headers([(header, "100.128.0.1")])).unwrap(),
+            "100.128.0.1".parse::<IpAddr>().unwrap(),
+            "just outside of CGNAT range"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! [`tonic`](https://docs.rs/tonic) gRPC interceptor
+//!
+//! [`ClientIpInterceptor`] runs a [`ClientIpConfig`] against request
+//! metadata and the [remote address](Request::remote_addr), and stores
+//! [`ClientIp`] into request extensions:
+//!
+//! ```
+//! use client_ip::{ClientIp, ClientIpConfig, ClientIpSource, tonic::ClientIpInterceptor};
+//!
+//! let interceptor = ClientIpInterceptor::new(
+//!     ClientIpConfig::new(ClientIpSource::XEnvoyExternalAddress)
+//!         .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+//! );
+//! // Wrap a generated service with
+//! // `FooServer::with_interceptor(service, interceptor)` and read the IP in
+//! // handlers with `request.extensions().get::<ClientIp>()`
+//! ```
+
+use http::HeaderName;
+use tonic::{Request, Status, metadata::MetadataMap, service::Interceptor};
+
+use crate::{ClientIp, ClientIpConfig, ClientIpRejection, HeaderAccess};
+
+impl HeaderAccess for MetadataMap {
+    fn header_values<'a>(&'a self, header_name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+        self.get_all(header_name.as_str())
+            .into_iter()
+            .map(|v| v.as_bytes())
+    }
+}
+
+/// A tonic [`Interceptor`] inserting [`ClientIp`] into request extensions
+///
+/// Rejects requests the client IP can't be extracted from with
+/// [`Status::invalid_argument`].
+#[derive(Debug, Clone)]
+pub struct ClientIpInterceptor {
+    config: ClientIpConfig,
+}
+
+impl ClientIpInterceptor {
+    /// Creates an interceptor resolving the client IP with the configuration
+    pub fn new(config: ClientIpConfig) -> Self {
+        Self { config }
+    }
+
+    /// Extracts the client IP of a request
+    pub fn extract<T>(&self, request: &Request<T>) -> Result<ClientIp, ClientIpRejection> {
+        let peer_ip = request.remote_addr().map(|addr| addr.ip());
+        Ok(ClientIp(self.config.extract(request.metadata(), peer_ip)?))
+    }
+}
+
+impl Interceptor for ClientIpInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let client_ip = self.extract(&request)?;
+        request.extensions_mut().insert(client_ip);
+        Ok(request)
+    }
+}
+
+impl From<ClientIpRejection> for Status {
+    fn from(rejection: ClientIpRejection) -> Self {
+        match rejection {
+            ClientIpRejection::Extraction(_) => Self::invalid_argument(rejection.to_string()),
+            ClientIpRejection::MissingSource | ClientIpRejection::MissingPeerAddr => {
+                Self::internal(rejection.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::{Code, transport::server::TcpConnectInfo};
+
+    use super::*;
+    use crate::ClientIpSource;
+
+    fn request(x_envoy_external_address: Option<&str>, remote_addr: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(ip) = x_envoy_external_address {
+            request
+                .metadata_mut()
+                .insert("x-envoy-external-address", ip.parse().unwrap());
+        }
+        request.extensions_mut().insert(TcpConnectInfo {
+            local_addr: None,
+            remote_addr: remote_addr.map(|addr| addr.parse().unwrap()),
+        });
+        request
+    }
+
+    fn call(interceptor: &ClientIpInterceptor, request: Request<()>) -> Result<ClientIp, Status> {
+        interceptor
+            .clone()
+            .call(request)
+            .map(|request| *request.extensions().get::<ClientIp>().unwrap())
+    }
+
+    #[test]
+    fn test_client_ip_interceptor() {
+        let config = ClientIpConfig::new(ClientIpSource::XEnvoyExternalAddress);
+        let interceptor = ClientIpInterceptor::new(config.clone());
+        assert_eq!(
+            call(&interceptor, request(Some("1.2.3.4"), None)).unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(
+            call(&interceptor, request(None, None)).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        let interceptor = ClientIpInterceptor::new(
+            config.trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+        );
+        assert_eq!(
+            call(&interceptor, request(Some("1.2.3.4"), None))
+                .unwrap_err()
+                .code(),
+            Code::Internal
+        );
+        assert_eq!(
+            call(
+                &interceptor,
+                request(Some("1.2.3.4"), Some("10.0.0.1:1234"))
+            )
+            .unwrap(),
+            ClientIp("1.2.3.4".parse().unwrap()),
+            "trusted proxy"
+        );
+        assert_eq!(
+            call(&interceptor, request(Some("1.2.3.4"), Some("5.6.7.8:1234"))).unwrap(),
+            ClientIp("5.6.7.8".parse().unwrap()),
+            "untrusted peer"
+        );
+    }
+}
@@ -0,0 +1,568 @@
+//! Detecting the deployment topology from sample requests
+//!
+//! [`TopologyDetector`] collects captured requests with their peer addresses
+//! and suggests sources that yield a globally routable IP for each of them,
+//! flagging IP headers that look controlled by clients:
+//!
+//! ```
+//! use client_ip::{ClientIpSource, topology::TopologyDetector};
+//! use http::HeaderMap;
+//!
+//! let mut detector = TopologyDetector::new();
+//! for (xff, real_ip) in [
+//!     ("1.1.1.1, 10.0.0.1", "10.0.0.1"),
+//!     ("2.2.2.2, 10.0.0.2", "10.0.0.2"),
+//! ] {
+//!     let mut headers = HeaderMap::new();
+//!     headers.insert("x-forwarded-for", xff.parse().unwrap());
+//!     headers.insert("x-real-ip", real_ip.parse().unwrap());
+//!     detector.add(&headers, "10.0.0.3".parse().unwrap());
+//! }
+//!
+//! let report = detector.report();
+//! assert_eq!(
+//!     report.suggestions,
+//!     [ClientIpSource::Traefik { trusted_proxies: 1 }]
+//! );
+//! ```
+//!
+//! Samples from globally routable peers look like direct client connections,
+//! where the client controls every header, so no source is suggested for a
+//! corpus containing them. Sources reading headers flagged as intermittent or
+//! repeated aren't suggested either, neither are ones skipping hops of a list
+//! header of varying length unless the hops are internal proxies in every
+//! sample. Envoy is only suggested when every sample carries
+//! `X-Envoy-External-Address`. Suggestions assume requests in the corpus come
+//! through the proxies, so pair them with
+//! [`TrustedProxies`](crate::TrustedProxies) unless the service is unreachable
+//! otherwise.
+
+use std::{collections::HashSet, fmt, net::IpAddr};
+
+use http::HeaderName;
+
+use crate::{
+    ClientIpSource, HeaderAccess, IpClass, classify, header_names::*, sanitize::IP_HEADER_NAMES,
+};
+
+/// Collects sample requests and analyzes them, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct TopologyDetector {
+    samples: usize,
+    direct_samples: usize,
+    peers: HashSet<IpAddr>,
+    headers: Vec<HeaderStats>,
+    sources: Vec<SourceReport>,
+    /// Parsed `X-Forwarded-For` entries of each sample containing the header
+    x_forwarded_for: Vec<Vec<Option<IpAddr>>>,
+}
+
+/// Result of analyzing sample requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TopologyReport {
+    /// Number of the samples
+    pub samples: usize,
+    /// Number of samples from globally routable peers, i.e. likely direct
+    /// clients
+    pub direct_samples: usize,
+    /// Number of distinct peer IPs
+    pub distinct_peers: usize,
+    /// IP headers found in the samples
+    pub headers: Vec<HeaderReport>,
+    /// Sources tried on the samples
+    pub sources: Vec<SourceReport>,
+    /// Sources yielding a globally routable IP for each sample from headers
+    /// that look safe, vendor specific headers first
+    pub suggestions: Vec<ClientIpSource>,
+}
+
+/// Occurrence of an IP header in the samples
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HeaderReport {
+    /// Header name
+    pub header_name: String,
+    /// Number of samples containing the header
+    pub present: usize,
+    /// Signs of the header being controlled by clients
+    pub flags: Vec<HeaderFlag>,
+}
+
+/// A sign of an IP header being controlled by clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HeaderFlag {
+    /// Missing in some samples, while a proxy sets it for every request
+    Intermittent,
+    /// A single value header occurs multiple times in some samples
+    Repeated,
+    /// A list header has a varying number of entries, so the leftmost ones
+    /// come from clients
+    VaryingLength,
+}
+
+/// Outcome of trying a source on the samples
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SourceReport {
+    /// The source
+    pub source: ClientIpSource,
+    /// Number of samples the source extracts an IP from
+    pub extracted: usize,
+    /// Number of extracted IPs that aren't globally routable
+    pub non_global: usize,
+    /// Flags of the headers the source takes the IP from
+    pub flags: Vec<HeaderFlag>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct HeaderStats {
+    present: usize,
+    repeated: usize,
+    lengths: HashSet<usize>,
+}
+
+/// Sources tried as is, vendor specific headers first
+///
+/// ingress-nginx is tried behind a single proxy, without one it reads
+/// `X-Forwarded-For` as `rightmost-x-forwarded-for` does. Traefik, i.e.
+/// `X-Forwarded-For` with trusted hops, is tried separately.
+fn sources() -> impl Iterator<Item = ClientIpSource> {
+    let (generic, vendor): (Vec<_>, Vec<_>) = ClientIpSource::ALL
+        .iter()
+        .filter(|source| !matches!(source, ClientIpSource::Traefik { .. }))
+        .map(|&source| match source {
+            ClientIpSource::IngressNginx { .. } => {
+                ClientIpSource::IngressNginx { trusted_proxies: 1 }
+            }
+            source => source,
+        })
+        .partition(|source| source.name().starts_with("rightmost-"));
+    vendor.into_iter().chain(generic)
+}
+
+impl TopologyDetector {
+    /// Creates a detector without samples
+    pub fn new() -> Self {
+        Self {
+            samples: 0,
+            direct_samples: 0,
+            peers: HashSet::new(),
+            headers: vec![HeaderStats::default(); IP_HEADER_NAMES.len()],
+            sources: sources()
+                .map(|source| SourceReport {
+                    source,
+                    extracted: 0,
+                    non_global: 0,
+                    flags: Vec::new(),
+                })
+                .collect(),
+            x_forwarded_for: Vec::new(),
+        }
+    }
+
+    /// Adds a sample request received from the peer
+    pub fn add(&mut self, header_map: &impl HeaderAccess, peer_ip: IpAddr) {
+        self.samples += 1;
+        if classify(peer_ip) == IpClass::Global {
+            self.direct_samples += 1;
+        }
+        self.peers.insert(peer_ip);
+
+        for (header_name, stats) in IP_HEADER_NAMES.iter().zip(&mut self.headers) {
+            let lines: Vec<&[u8]> = header_map.header_values(header_name).collect();
+            if lines.is_empty() {
+                continue;
+            }
+            stats.present += 1;
+            if lines.len() > 1 {
+                stats.repeated += 1;
+            }
+            stats.lengths.insert(
+                lines
+                    .iter()
+                    .map(|line| line.split(|&b| b == b',').count())
+                    .sum(),
+            );
+        }
+
+        for report in &mut self.sources {
+            if let Ok(ip) = report.source.extract(header_map) {
+                report.extracted += 1;
+                if classify(ip) != IpClass::Global {
+                    report.non_global += 1;
+                }
+            }
+        }
+
+        if let Ok(entries) = crate::list_header_entries(header_map, &X_FORWARDED_FOR) {
+            self.x_forwarded_for.push(
+                entries
+                    .into_iter()
+                    .map(|(_, entry)| entry.parse().ok())
+                    .collect(),
+            );
+        }
+    }
+
+    /// Analyzes the samples
+    pub fn report(&self) -> TopologyReport {
+        let headers: Vec<HeaderReport> = IP_HEADER_NAMES
+            .iter()
+            .zip(&self.headers)
+            .filter(|(_, stats)| stats.present > 0)
+            .map(|(header_name, stats)| {
+                let is_list =
+                    [X_FORWARDED_FOR, X_ORIGINAL_FORWARDED_FOR, FORWARDED].contains(header_name);
+                let mut flags = Vec::new();
+                if stats.present < self.samples {
+                    flags.push(HeaderFlag::Intermittent);
+                }
+                if stats.repeated > 0 && !is_list {
+                    flags.push(HeaderFlag::Repeated);
+                }
+                if stats.lengths.len() > 1 && is_list {
+                    flags.push(HeaderFlag::VaryingLength);
+                }
+                HeaderReport {
+                    header_name: header_name.as_str().to_owned(),
+                    present: stats.present,
+                    flags,
+                }
+            })
+            .collect();
+
+        let internal_hops = self.x_forwarded_for_trusted_hops();
+        let mut sources = self.sources.clone();
+        if let Some(trusted_hops) = internal_hops {
+            sources.push(self.x_forwarded_for_report(trusted_hops));
+        }
+        for report in &mut sources {
+            report.flags = header_flags(&report.source, &headers);
+        }
+
+        let in_every_sample = |header_name: &HeaderName| {
+            headers.iter().any(|header| {
+                header.header_name == header_name.as_str() && header.present == self.samples
+            })
+        };
+        let suggestions = sources
+            .iter()
+            .filter(|report| {
+                self.samples > 0
+                    && self.direct_samples == 0
+                    && report.extracted == self.samples
+                    && report.non_global == 0
+                    && !report.flags.contains(&HeaderFlag::Intermittent)
+                    && !report.flags.contains(&HeaderFlag::Repeated)
+                    && (!report.flags.contains(&HeaderFlag::VaryingLength)
+                        || trusted_hops(&report.source) <= internal_hops.unwrap_or(0))
+                    && match report.source {
+                        ClientIpSource::Envoy { .. } => in_every_sample(&X_ENVOY_EXTERNAL_ADDRESS),
+                        _ => true,
+                    }
+            })
+            .map(|report| report.source)
+            .collect();
+
+        TopologyReport {
+            samples: self.samples,
+            direct_samples: self.direct_samples,
+            distinct_peers: self.peers.len(),
+            headers,
+            sources,
+            suggestions,
+        }
+    }
+
+    /// Number of rightmost `X-Forwarded-For` entries holding non-global IPs
+    /// in every sample, i.e. likely added by internal proxies, leaving at
+    /// least one entry for the client
+    fn x_forwarded_for_trusted_hops(&self) -> Option<usize> {
+        self.x_forwarded_for
+            .iter()
+            .map(|entries| {
+                let internal = entries
+                    .iter()
+                    .rev()
+                    .take_while(|ip| ip.is_some_and(|ip| classify(ip) != IpClass::Global))
+                    .count();
+                internal.min(entries.len().saturating_sub(1))
+            })
+            .min()
+            .filter(|&trusted_hops| trusted_hops > 0)
+    }
+
+    fn x_forwarded_for_report(&self, trusted_hops: usize) -> SourceReport {
+        let ips: Vec<IpAddr> = self
+            .x_forwarded_for
+            .iter()
+            .filter_map(|entries| entries.iter().rev().nth(trusted_hops).copied().flatten())
+            .collect();
+        SourceReport {
+            source: ClientIpSource::Traefik {
+                trusted_proxies: trusted_hops,
+            },
+            extracted: ips.len(),
+            non_global: ips
+                .iter()
+                .filter(|&&ip| classify(ip) != IpClass::Global)
+                .count(),
+            flags: Vec::new(),
+        }
+    }
+}
+
+/// Flags of the headers the source takes the IP from, `X-Envoy-Internal` is
+/// only a marker
+fn header_flags(source: &ClientIpSource, headers: &[HeaderReport]) -> Vec<HeaderFlag> {
+    let mut flags = Vec::new();
+    for header_name in source.header_names() {
+        if header_name == X_ENVOY_INTERNAL {
+            continue;
+        }
+        let header = headers
+            .iter()
+            .find(|header| header.header_name == header_name.as_str());
+        for &flag in header.map_or(&[][..], |header| &header.flags) {
+            if !flags.contains(&flag) {
+                flags.push(flag);
+            }
+        }
+    }
+    flags
+}
+
+/// Number of list entries the source skips from the right
+fn trusted_hops(source: &ClientIpSource) -> usize {
+    match *source {
+        ClientIpSource::Envoy {
+            xff_num_trusted_hops,
+        } => xff_num_trusted_hops,
+        ClientIpSource::IngressNginx { trusted_proxies } => trusted_proxies.saturating_sub(1),
+        ClientIpSource::Traefik { trusted_proxies } => trusted_proxies,
+        _ => 0,
+    }
+}
+
+impl Default for TopologyDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for TopologyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "samples: {}, from global peers: {}, distinct peers: {}",
+            self.samples, self.direct_samples, self.distinct_peers
+        )?;
+        for header in &self.headers {
+            write!(
+                f,
+                "header {}: present in {}",
+                header.header_name, header.present
+            )?;
+            write_flags(f, &header.flags)?;
+            writeln!(f)?;
+        }
+        for source in &self.sources {
+            write!(
+                f,
+                "source {}: extracted {}, non-global {}",
+                source.source, source.extracted, source.non_global
+            )?;
+            write_flags(f, &source.flags)?;
+            writeln!(f)?;
+        }
+        if self.direct_samples > 0 {
+            return writeln!(f, "suggestions: none, direct clients, use the peer IP");
+        }
+        if self.suggestions.is_empty() {
+            return writeln!(f, "suggestions: none, use the peer IP");
+        }
+        write!(f, "suggestions:")?;
+        for (i, source) in self.suggestions.iter().enumerate() {
+            write!(f, "{} {source}", if i == 0 { "" } else { "," })?;
+        }
+        writeln!(f)
+    }
+}
+
+fn write_flags(f: &mut fmt::Formatter<'_>, flags: &[HeaderFlag]) -> fmt::Result {
+    for flag in flags {
+        f.write_str(match flag {
+            HeaderFlag::Intermittent => ", intermittent",
+            HeaderFlag::Repeated => ", repeated",
+            HeaderFlag::VaryingLength => ", varying length",
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+
+    fn headers<'a>(items: impl IntoIterator<Item = (&'a str, &'a str)>) -> HeaderMap {
+        items
+            .into_iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_report() {
+        let peer_ip = "10.0.0.3".parse().unwrap();
+
+        let mut detector = TopologyDetector::new();
+        detector.add(
+            &headers([
+                ("cf-connecting-ip", "1.1.1.1"),
+                ("x-forwarded-for", "1.1.1.1"),
+                ("x-real-ip", "1.1.1.1"),
+            ]),
+            peer_ip,
+        );
+        detector.add(
+            &headers([
+                ("cf-connecting-ip", "2.2.2.2"),
+                ("x-forwarded-for", "6.6.6.6, 2.2.2.2"),
+                ("x-real-ip", "2.2.2.2"),
+                ("x-real-ip", "6.6.6.6"),
+                ("true-client-ip", "6.6.6.6"),
+            ]),
+            peer_ip,
+        );
+
+        let report = detector.report();
+        assert_eq!(report.samples, 2);
+        assert_eq!(report.direct_samples, 0);
+        assert_eq!(report.distinct_peers, 1);
+        assert_eq!(
+            report
+                .headers
+                .iter()
+                .map(|header| (header.header_name.as_str(), header.flags.as_slice()))
+                .collect::<Vec<_>>(),
+            [
+                ("cf-connecting-ip", &[][..]),
+                ("true-client-ip", &[HeaderFlag::Intermittent]),
+                ("x-forwarded-for", &[HeaderFlag::VaryingLength]),
+                ("x-real-ip", &[HeaderFlag::Repeated]),
+            ]
+        );
+        assert_eq!(
+            report.suggestions,
+            [
+                ClientIpSource::CfConnectingIp,
+                ClientIpSource::RightmostXForwardedFor
+            ]
+        );
+        let text = report.to_string();
+        assert!(text.contains("\nheader true-client-ip: present in 1, intermittent\n"));
+        assert!(text.ends_with(
+            "\
+source rightmost-x-forwarded-for: extracted 2, non-global 0, varying length
+suggestions: cf-connecting-ip, rightmost-x-forwarded-for
+"
+        ));
+
+        detector.add(
+            &headers([
+                ("cf-connecting-ip", "3.3.3.3"),
+                ("x-forwarded-for", "3.3.3.3"),
+            ]),
+            "3.3.3.3".parse().unwrap(),
+        );
+        let report = detector.report();
+        assert_eq!(report.direct_samples, 1);
+        assert!(report.suggestions.is_empty(), "direct client");
+        assert!(
+            report
+                .to_string()
+                .ends_with("suggestions: none, direct clients, use the peer IP\n")
+        );
+    }
+
+    #[test]
+    fn test_report_envoy() {
+        let peer_ip = "10.0.0.3".parse().unwrap();
+        let mut detector = TopologyDetector::new();
+        detector.add(&headers([("x-forwarded-for", "1.1.1.1")]), peer_ip);
+        assert_eq!(
+            detector.report().suggestions,
+            [ClientIpSource::RightmostXForwardedFor]
+        );
+
+        detector.add(
+            &headers([
+                ("x-forwarded-for", "2.2.2.2"),
+                ("x-envoy-external-address", "2.2.2.2"),
+            ]),
+            peer_ip,
+        );
+        assert_eq!(
+            detector.report().suggestions,
+            [ClientIpSource::RightmostXForwardedFor],
+            "intermittent X-Envoy-External-Address"
+        );
+
+        let mut detector = TopologyDetector::new();
+        for ip in ["1.1.1.1", "2.2.2.2"] {
+            detector.add(
+                &headers([("x-forwarded-for", ip), ("x-envoy-external-address", ip)]),
+                peer_ip,
+            );
+        }
+        assert_eq!(
+            detector.report().suggestions,
+            [
+                ClientIpSource::Envoy {
+                    xff_num_trusted_hops: 0
+                },
+                ClientIpSource::XEnvoyExternalAddress,
+                ClientIpSource::RightmostXForwardedFor
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_trusted_hops() {
+        let mut detector = TopologyDetector::new();
+        assert!(detector.report().suggestions.is_empty());
+
+        let peer_ip = "10.0.0.3".parse().unwrap();
+        for xff in [
+            "1.1.1.1, 10.0.0.1, 10.0.0.2",
+            "6.6.6.6, 2.2.2.2, 10.0.0.1, 10.0.0.2",
+        ] {
+            detector.add(&headers([("x-forwarded-for", xff)]), peer_ip);
+        }
+        assert_eq!(
+            detector.report().suggestions,
+            [ClientIpSource::Traefik { trusted_proxies: 2 }]
+        );
+
+        detector.add(
+            &headers([("x-forwarded-for", "192.168.0.1, 10.0.0.1, 10.0.0.2")]),
+            peer_ip,
+        );
+        let report = detector.report();
+        assert_eq!(
+            report.sources.last(),
+            Some(&SourceReport {
+                source: ClientIpSource::Traefik { trusted_proxies: 2 },
+                extracted: 3,
+                non_global: 1,
+                flags: vec![HeaderFlag::VaryingLength],
+            })
+        );
+        assert!(report.suggestions.is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+//! [`tower`](https://docs.rs/tower) middleware inserting the client IP into
+//! request extensions
+//!
+//! ```
+//! use client_ip::{ClientIp, ClientIpSource, tower::ClientIpLayer};
+//! use tower::{ServiceBuilder, service_fn};
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(ClientIpLayer::new(ClientIpSource::RightmostXForwardedFor))
+//!     .service(service_fn(|req: http::Request<()>| async move {
+//!         let ClientIp(ip) = req.extensions().get::<ClientIp>().copied().unwrap();
+//!         Ok::<_, std::convert::Infallible>(http::Response::new(ip.to_string()))
+//!     }));
+//! ```
+//!
+//! With [trusted proxies](ClientIpConfig::trusted_proxies) configured, the
+//! peer IP is read from a [`SocketAddr`] request extension by default, see
+//! [`ClientIpLayer::peer_ip`] for other connection info types.
+
+use std::{
+    future::Future,
+    mem,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Extensions, Request, Response};
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{ClientIp, ClientIpConfig};
+
+/// What to do with requests the client IP can't be extracted from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnError {
+    /// Respond with the [status](crate::ClientIpRejection::status) of the
+    /// rejection
+    /// without calling the inner service
+    ///
+    /// The inner service driven to readiness is then dropped and replaced
+    /// with a clone, releasing capacity it reserved, e.g. a concurrency limit
+    /// permit.
+    #[default]
+    Reject,
+    /// Call the inner service with the
+    /// [`ClientIpRejection`](crate::ClientIpRejection) inserted into request
+    /// extensions instead of [`ClientIp`]
+    PassThrough,
+}
+
+/// A [`Layer`] inserting [`ClientIp`] extracted according to a
+/// [`ClientIpConfig`] into request extensions
+#[derive(Debug, Clone)]
+pub struct ClientIpLayer {
+    config: ClientIpConfig,
+    on_error: OnError,
+    peer_ip: fn(&Extensions) -> Option<IpAddr>,
+}
+
+impl ClientIpLayer {
+    /// Creates a layer rejecting requests without a valid client IP
+    pub fn new(config: impl Into<ClientIpConfig>) -> Self {
+        Self {
+            config: config.into(),
+            on_error: OnError::default(),
+            peer_ip: socket_addr_ip,
+        }
+    }
+
+    /// Sets what to do with requests the client IP can't be extracted from
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    /// Sets how to read the peer IP from request extensions, e.g. from
+    /// `axum::extract::ConnectInfo<SocketAddr>`
+    pub fn peer_ip(mut self, peer_ip: fn(&Extensions) -> Option<IpAddr>) -> Self {
+        self.peer_ip = peer_ip;
+        self
+    }
+}
+
+fn socket_addr_ip(extensions: &Extensions) -> Option<IpAddr> {
+    extensions.get::<SocketAddr>().map(SocketAddr::ip)
+}
+
+impl<S> Layer<S> for ClientIpLayer {
+    type Service = ClientIpService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientIpService {
+            inner,
+            config: self.config.clone(),
+            on_error: self.on_error,
+            peer_ip: self.peer_ip,
+        }
+    }
+}
+
+/// A [`Service`] created by [`ClientIpLayer`]
+#[derive(Debug, Clone)]
+pub struct ClientIpService<S> {
+    inner: S,
+    config: ClientIpConfig,
+    on_error: OnError,
+    peer_ip: fn(&Extensions) -> Option<IpAddr>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ClientIpService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let peer_ip = (self.peer_ip)(req.extensions());
+        match (self.config.extract(req.headers(), peer_ip), self.on_error) {
+            (Ok(ip), _) => {
+                req.extensions_mut().insert(ClientIp(ip));
+            }
+            (Err(rejection), OnError::PassThrough) => {
+                req.extensions_mut().insert(rejection);
+            }
+            (Err(rejection), OnError::Reject) => {
+                let clone = self.inner.clone();
+                drop(mem::replace(&mut self.inner, clone));
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = rejection.status();
+                return ResponseFuture {
+                    kind: Kind::Rejected {
+                        response: Some(res),
+                    },
+                };
+            }
+        }
+        ResponseFuture {
+            kind: Kind::Inner {
+                future: self.inner.call(req),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of [`ClientIpService`]
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        kind: Kind<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = KindProj]
+    enum Kind<F, B> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().kind.project() {
+            KindProj::Inner { future } => future.poll(cx),
+            KindProj::Rejected { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, task::Waker};
+
+    use http::{HeaderName, StatusCode};
+    use tower::{ServiceExt, limit::ConcurrencyLimitLayer, service_fn};
+
+    use super::*;
+    use crate::{Canonicalization, ClientIpRejection, ClientIpSource, Error};
+
+    async fn echo(req: Request<()>) -> Result<Response<String>, Infallible> {
+        let extensions = req.extensions();
+        Ok(Response::new(format!(
+            "{:?} {:?}",
+            extensions.get::<ClientIp>(),
+            extensions.get::<ClientIpRejection>()
+        )))
+    }
+
+    fn request(x_real_ip: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder();
+        if let Some(ip) = x_real_ip {
+            builder = builder.header("x-real-ip", ip);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_layer() {
+        let layer = ClientIpLayer::new(ClientIpSource::XRealIp);
+
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(request(Some("1.2.3.4")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.body(),
+            &format!("{:?} None", Some(ClientIp("1.2.3.4".parse().unwrap())))
+        );
+
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(request(Some("::ffff:1.2.3.4")))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.body(),
+            &format!("{:?} None", Some(ClientIp("1.2.3.4".parse().unwrap()))),
+            "IPv4-mapped"
+        );
+
+        let res = ClientIpLayer::new(
+            ClientIpConfig::new(ClientIpSource::XRealIp).canonicalization(Canonicalization {
+                nat64: true,
+                ..Default::default()
+            }),
+        )
+        .layer(service_fn(echo))
+        .oneshot(request(Some("64:ff9b::102:304")))
+        .await
+        .unwrap();
+        assert_eq!(
+            res.body(),
+            &format!("{:?} None", Some(ClientIp("1.2.3.4".parse().unwrap()))),
+            "NAT64"
+        );
+
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(request(None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.body(), "");
+
+        let res = layer
+            .clone()
+            .on_error(OnError::PassThrough)
+            .layer(service_fn(echo))
+            .oneshot(request(None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.body(),
+            &format!(
+                "None {:?}",
+                Some(ClientIpRejection::Extraction(Error::AbsentHeader {
+                    header_name: HeaderName::from_static("x-real-ip")
+                }))
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trusted_proxies() {
+        let layer = ClientIpLayer::new(
+            ClientIpConfig::new(ClientIpSource::XRealIp)
+                .trusted_proxies(["10.0.0.0/8".parse().unwrap()].into_iter().collect()),
+        );
+        let request = |peer_addr: Option<&str>| {
+            let mut req = request(Some("1.2.3.4"));
+            if let Some(addr) = peer_addr {
+                req.extensions_mut()
+                    .insert(addr.parse::<SocketAddr>().unwrap());
+            }
+            req
+        };
+
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(request(Some("10.0.0.1:1234")))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.body(),
+            &format!("{:?} None", Some(ClientIp("1.2.3.4".parse().unwrap()))),
+            "trusted proxy"
+        );
+
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(request(Some("5.6.7.8:1234")))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.body(),
+            &format!("{:?} None", Some(ClientIp("5.6.7.8".parse().unwrap()))),
+            "untrusted peer"
+        );
+
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(request(None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let res = layer
+            .clone()
+            .peer_ip(|_| Some("5.6.7.8".parse().unwrap()))
+            .layer(service_fn(echo))
+            .oneshot(request(None))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.body(),
+            &format!("{:?} None", Some(ClientIp("5.6.7.8".parse().unwrap()))),
+            "custom peer IP"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_releases_readiness() {
+        let mut service = ClientIpLayer::new(ClientIpSource::XRealIp)
+            .layer(ConcurrencyLimitLayer::new(1).layer(service_fn(echo)));
+        let mut other = service.clone();
+
+        let res = service.ready().await.unwrap().call(request(None)).await;
+        assert_eq!(res.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert!(
+            other
+                .poll_ready(&mut Context::from_waker(Waker::noop()))
+                .is_ready()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use crate::{
+    ClientIpSource, HeaderAccess, Result,
+    explain::{Explanation, Extractor, Recorder, Tracer},
+};
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`
+///
+/// A bare IP is parsed as a single host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Creates a network, returns `None` if the prefix length exceeds the
+    /// address length
+    ///
+    /// IPv4-mapped IPv6 networks are converted to IPv4, e.g.
+    /// `::ffff:10.0.0.0/104` to `10.0.0.0/8`. Their prefix must cover the
+    /// `::ffff:0:0/96` range, otherwise `None` is returned.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        if prefix_len > max_prefix_len(addr) {
+            return None;
+        }
+        match addr {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => Some(Self {
+                    addr: v4.into(),
+                    prefix_len: prefix_len.checked_sub(96)?,
+                }),
+                None => Some(Self { addr, prefix_len }),
+            },
+            IpAddr::V4(_) => Some(Self { addr, prefix_len }),
+        }
+    }
+
+    /// Checks if the network contains the IP, IPv4-mapped IPv6 addresses are
+    /// treated as IPv4
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_eq(
+                u32::from(net).into(),
+                u32::from(ip).into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(net.into(), ip.into(), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        let addr = addr.to_canonical();
+        Self {
+            addr,
+            prefix_len: max_prefix_len(addr),
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidIpNetwork;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || InvalidIpNetwork(s.to_owned());
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => Self::new(
+                addr.parse().map_err(|_| invalid())?,
+                prefix_len.parse().map_err(|_| invalid())?,
+            )
+            .ok_or_else(invalid),
+            None => s.parse::<IpAddr>().map(Self::from).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Error of parsing an [`IpNetwork`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidIpNetwork(String);
+
+impl fmt::Display for InvalidIpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid IP network: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidIpNetwork {}
+
+/// Networks of proxies trusted to set client IP headers
+///
+/// Requests coming directly from other peers can carry arbitrary headers, so
+/// the peer itself is considered the client.
+///
+/// ```
+/// use client_ip::{ClientIpSource, TrustedProxies};
+///
+/// let trusted_proxies: TrustedProxies = ["10.0.0.0/8", "fd00::/8"]
+///     .into_iter()
+///     .map(|s| s.parse().unwrap())
+///     .collect();
+/// let header_map = http::HeaderMap::from_iter([(
+///     http::header::HeaderName::from_static("x-real-ip"),
+///     http::HeaderValue::from_static("1.2.3.4"),
+/// )]);
+///
+/// let from_proxy = trusted_proxies.extract(
+///     &ClientIpSource::XRealIp,
+///     &header_map,
+///     "10.0.0.1".parse().unwrap(),
+/// );
+/// assert_eq!(from_proxy.unwrap().to_string(), "1.2.3.4");
+///
+/// let direct = trusted_proxies.extract(
+///     &ClientIpSource::XRealIp,
+///     &header_map,
+///     "5.6.7.8".parse().unwrap(),
+/// );
+/// assert_eq!(direct.unwrap().to_string(), "5.6.7.8");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpNetwork>);
+
+impl TrustedProxies {
+    /// Creates a list of trusted proxy networks
+    pub fn new(networks: impl IntoIterator<Item = IpNetwork>) -> Self {
+        Self(networks.into_iter().collect())
+    }
+
+    /// Checks if the IP belongs to a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// Extracts the client IP using the source if the peer is a trusted
+    /// proxy, returns the peer IP otherwise
+    pub fn extract(
+        &self,
+        source: &ClientIpSource,
+        header_map: &impl HeaderAccess,
+        peer_ip: IpAddr,
+    ) -> Result<IpAddr> {
+        self.extract_traced(source, header_map, peer_ip, &mut ())
+    }
+
+    /// Extracts the client IP the same way as [`extract`](Self::extract),
+    /// tracing the decision on the peer and the headers
+    pub fn explain(
+        &self,
+        source: &ClientIpSource,
+        header_map: &impl HeaderAccess,
+        peer_ip: IpAddr,
+    ) -> Explanation {
+        let mut recorder = Recorder::default();
+        let result = self.extract_traced(source, header_map, peer_ip, &mut recorder);
+        recorder.finish(Extractor::Source(*source), result)
+    }
+
+    pub(crate) fn extract_traced(
+        &self,
+        source: &ClientIpSource,
+        header_map: &impl HeaderAccess,
+        peer_ip: IpAddr,
+        tracer: &mut impl Tracer,
+    ) -> Result<IpAddr> {
+        let trusted = self.contains(peer_ip);
+        tracer.peer(peer_ip, trusted);
+        if trusted {
+            source.extract_traced(header_map, tracer)
+        } else {
+            Ok(peer_ip)
+        }
+    }
+}
+
+impl FromIterator<IpNetwork> for TrustedProxies {
+    fn from_iter<T: IntoIterator<Item = IpNetwork>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
+
+fn max_prefix_len(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn prefix_eq(a: u128, b: u128, bits: u8, prefix_len: u8) -> bool {
+    let host_bits = u32::from(bits - prefix_len);
+    a.checked_shr(host_bits).unwrap_or(0) == b.checked_shr(host_bits).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip_network_parse() {
+        assert_eq!(network("10.0.0.0/8").to_string(), "10.0.0.0/8");
+        assert_eq!(network("10.1.2.3").to_string(), "10.1.2.3/32");
+        assert_eq!(network("::ffff:10.0.0.0/104").to_string(), "10.0.0.0/8");
+        assert_eq!(network("::ffff:0:0/96").to_string(), "0.0.0.0/0");
+        assert_eq!(network("::ffff:10.1.2.3").to_string(), "10.1.2.3/32");
+        assert_eq!(network("fd00::/8").to_string(), "fd00::/8");
+
+        for s in [
+            "foo",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/x",
+            "::ffff:10.0.0.0/8",
+            "::ffff:0:0/95",
+        ] {
+            assert_eq!(
+                s.parse::<IpNetwork>().unwrap_err(),
+                InvalidIpNetwork(s.into()),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ip_network_contains() {
+        for (net, ip, expected) in [
+            ("10.0.0.0/8", "10.255.0.1", true),
+            ("10.0.0.0/8", "11.0.0.1", false),
+            ("10.0.0.0/8", "::ffff:10.0.0.1", true),
+            ("::ffff:10.0.0.0/104", "10.0.0.1", true),
+            ("::ffff:10.0.0.0/104", "11.0.0.1", false),
+            ("::ffff:0:0/96", "1.2.3.4", true),
+            ("::ffff:0:0/96", "::1", false),
+            ("10.1.2.3", "10.1.2.3", true),
+            ("10.1.2.3", "10.1.2.4", false),
+            ("0.0.0.0/0", "1.2.3.4", true),
+            ("0.0.0.0/0", "::1", false),
+            ("fd00::/8", "fd12::1", true),
+            ("fd00::/8", "fe80::1", false),
+            ("::/0", "2001:db8::1", true),
+        ] {
+            assert_eq!(
+                network(net).contains(ip.parse().unwrap()),
+                expected,
+                "{net} {ip}"
+            );
+        }
+    }
+}